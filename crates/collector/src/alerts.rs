@@ -0,0 +1,355 @@
+use agent_config::types::AlertRuleConfig;
+use agent_core::alerts::{Alert, AlertComparator, AlertEvent, AlertState};
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, SystemTime};
+
+use crate::helpers::matches_pattern;
+
+/// Time elapsed between two times, zero if the clock went backwards
+fn elapsed(since: SystemTime, now: SystemTime) -> Duration {
+    now.duration_since(since).unwrap_or_default()
+}
+
+/// Checks alert rules against each capture
+pub(crate) struct AlertEngine {
+    rules: Vec<AlertRuleConfig>,
+    /// Pending & firing alerts, by rule index & metric path
+    active: HashMap<(usize, String), Alert>,
+}
+
+impl AlertEngine {
+    pub fn new(rules: Vec<AlertRuleConfig>) -> Self {
+        for rule in &rules {
+            let clear = match rule.clear_threshold {
+                Some(v) => v,
+                None => continue,
+            };
+            // a stricter clear threshold would resolve alerts that are still breached
+            let valid = match rule.comparator {
+                AlertComparator::GreaterThan | AlertComparator::GreaterThanOrEqual => {
+                    clear <= rule.threshold
+                }
+                AlertComparator::LessThan | AlertComparator::LessThanOrEqual => {
+                    clear >= rule.threshold
+                }
+                AlertComparator::Equal | AlertComparator::NotEqual => clear == rule.threshold,
+            };
+            if !valid {
+                log::warn!(
+                    "alert '{}' clear threshold should be less strict than its threshold",
+                    rule.name
+                );
+            }
+        }
+        Self {
+            rules,
+            active: HashMap::new(),
+        }
+    }
+    /// Pending & firing alerts
+    pub fn active(&self) -> Vec<Alert> {
+        let mut alerts: Vec<Alert> = self.active.values().cloned().collect();
+        alerts.sort_by(|a, b| (&a.rule, &a.metric).cmp(&(&b.rule, &b.metric)));
+        alerts
+    }
+    /// Check every rule against the numeric values of a capture, by their path in the metrics.
+    /// Returns alerts that need notifying
+    pub fn evaluate(&mut self, values: &BTreeMap<String, f64>, now: SystemTime) -> Vec<AlertEvent> {
+        if self.rules.is_empty() {
+            return vec![];
+        }
+        let mut events = vec![];
+        for (index, rule) in self.rules.iter().enumerate() {
+            let clear_threshold = rule.clear_threshold.unwrap_or(rule.threshold);
+
+            // metrics newly breaching the threshold start pending
+            for (metric, value) in values
+                .iter()
+                .filter(|(metric, _)| matches_pattern(&rule.metric, metric))
+                .filter(|(_, value)| rule.comparator.compare(**value, rule.threshold))
+            {
+                self.active
+                    .entry((index, metric.to_string()))
+                    .or_insert_with(|| {
+                        log::debug!("alert '{}' pending for '{metric}'", rule.name);
+                        Alert {
+                            rule: rule.name.clone(),
+                            metric: metric.to_string(),
+                            comparator: rule.comparator,
+                            threshold: rule.threshold,
+                            clear_threshold,
+                            severity: rule.severity,
+                            state: AlertState::Pending,
+                            value: *value,
+                            started_at: now,
+                            fired_at: None,
+                            notified_at: None,
+                            resolved_at: None,
+                        }
+                    });
+            }
+
+            let keys: Vec<(usize, String)> = self
+                .active
+                .keys()
+                .filter(|key| key.0 == index)
+                .cloned()
+                .collect();
+            for key in keys {
+                let alert = self.active.get_mut(&key).expect("alert is active");
+                let value = values.get(&key.1).copied();
+                if let Some(value) = value {
+                    alert.value = value;
+                }
+                // resolved alerts are removed, so active alerts are either pending or firing
+                if alert.state == AlertState::Pending {
+                    // pending alerts are dropped without notifying once no longer breached
+                    if !matches!(value, Some(v) if rule.comparator.compare(v, rule.threshold)) {
+                        log::debug!("alert '{}' no longer pending for '{}'", rule.name, key.1);
+                        self.active.remove(&key);
+                    } else if elapsed(alert.started_at, now) >= Duration::from_secs(rule.duration) {
+                        log::info!("alert '{}' firing for '{}'", rule.name, key.1);
+                        alert.state = AlertState::Firing;
+                        alert.fired_at = Some(now);
+                        alert.notified_at = Some(now);
+                        events.push(AlertEvent::Firing(alert.clone()));
+                    }
+                    continue;
+                }
+                // firing alerts stay firing until past the clear threshold, or the metric is gone
+                if !matches!(value, Some(v) if rule.comparator.compare(v, clear_threshold)) {
+                    log::info!("alert '{}' resolved for '{}'", rule.name, key.1);
+                    let mut alert = self.active.remove(&key).expect("alert is active");
+                    alert.state = AlertState::Resolved;
+                    alert.resolved_at = Some(now);
+                    events.push(AlertEvent::Resolved(alert));
+                    continue;
+                }
+                let renotify = rule.renotify.map(Duration::from_secs);
+                let notified_at = alert.notified_at.unwrap_or(now);
+                if matches!(renotify, Some(v) if elapsed(notified_at, now) >= v) {
+                    log::info!("alert '{}' still firing for '{}'", rule.name, key.1);
+                    alert.notified_at = Some(now);
+                    events.push(AlertEvent::Firing(alert.clone()));
+                }
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use agent_core::alerts::AlertSeverity;
+    use agent_core::metrics::{CapturedMetrics, MemoryMetrics, Metrics};
+    use std::time::UNIX_EPOCH;
+
+    use crate::helpers::numeric_values;
+
+    const METRIC: &str = "memory.perc_used";
+
+    fn rule(clear_threshold: Option<f64>, duration: u64, renotify: Option<u64>) -> AlertRuleConfig {
+        AlertRuleConfig {
+            name: "high-memory".to_string(),
+            metric: METRIC.to_string(),
+            comparator: AlertComparator::GreaterThan,
+            threshold: 90.0,
+            clear_threshold,
+            duration,
+            renotify,
+            severity: AlertSeverity::Critical,
+        }
+    }
+
+    /// Capture with the given memory use, taken the given seconds after the epoch
+    fn capture(seconds: u64, perc_used: Option<f32>) -> CapturedMetrics {
+        let memory = perc_used.map(|perc_used| MemoryMetrics {
+            perc_used,
+            detailed: None,
+            swap: None,
+        });
+        CapturedMetrics {
+            captured_at: UNIX_EPOCH + Duration::from_secs(seconds),
+            metrics: Metrics {
+                memory,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Evaluate a sequence of captures, returning the events of each as "firing" or "resolved"
+    fn evaluate_all(
+        engine: &mut AlertEngine,
+        captures: &[CapturedMetrics],
+    ) -> Vec<Vec<&'static str>> {
+        captures
+            .iter()
+            .map(|captured| {
+                let metrics = serde_json::to_value(&captured.metrics).unwrap();
+                let mut values = BTreeMap::new();
+                numeric_values("", &metrics, &mut values);
+                engine
+                    .evaluate(&values, captured.captured_at)
+                    .iter()
+                    .map(|event| match event {
+                        AlertEvent::Firing(_) => "firing",
+                        AlertEvent::Resolved(_) => "resolved",
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn state(engine: &AlertEngine) -> Option<AlertState> {
+        engine.active().first().map(|alert| alert.state)
+    }
+
+    #[test]
+    fn fires_once_breached_for_the_duration() {
+        let mut engine = AlertEngine::new(vec![rule(None, 60, None)]);
+        let events = evaluate_all(
+            &mut engine,
+            &[
+                capture(0, Some(95.0)),
+                capture(30, Some(95.0)),
+                capture(59, Some(95.0)),
+            ],
+        );
+        assert!(events.iter().all(Vec::is_empty));
+        assert_eq!(state(&engine), Some(AlertState::Pending));
+
+        let events = evaluate_all(&mut engine, &[capture(60, Some(95.0))]);
+        assert_eq!(events, vec![vec!["firing"]]);
+        let alert = &engine.active()[0];
+        assert_eq!(alert.state, AlertState::Firing);
+        assert_eq!(alert.metric, METRIC);
+        assert_eq!(alert.started_at, UNIX_EPOCH);
+        assert_eq!(alert.fired_at, Some(UNIX_EPOCH + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn pending_is_dropped_without_notifying() {
+        let mut engine = AlertEngine::new(vec![rule(None, 60, None)]);
+        let events = evaluate_all(
+            &mut engine,
+            &[
+                capture(0, Some(95.0)),
+                capture(30, Some(85.0)),
+                // breaching again restarts the duration
+                capture(40, Some(95.0)),
+                capture(90, Some(95.0)),
+            ],
+        );
+        assert!(events.iter().all(Vec::is_empty));
+        assert_eq!(
+            engine.active()[0].started_at,
+            UNIX_EPOCH + Duration::from_secs(40)
+        );
+        assert_eq!(
+            evaluate_all(&mut engine, &[capture(100, Some(95.0))]),
+            vec![vec!["firing"]]
+        );
+    }
+
+    #[test]
+    fn flapping_around_the_threshold_does_not_resolve() {
+        let mut engine = AlertEngine::new(vec![rule(Some(80.0), 0, None)]);
+        let events = evaluate_all(
+            &mut engine,
+            &[
+                capture(0, Some(91.0)),
+                capture(1, Some(89.0)),
+                capture(2, Some(91.0)),
+                capture(3, Some(85.0)),
+                capture(4, Some(92.0)),
+                capture(5, Some(81.0)),
+            ],
+        );
+        assert_eq!(
+            events,
+            vec![vec!["firing"], vec![], vec![], vec![], vec![], vec![]]
+        );
+        assert_eq!(state(&engine), Some(AlertState::Firing));
+        assert_eq!(engine.active()[0].value, 81.0);
+    }
+
+    #[test]
+    fn resolves_at_the_clear_threshold() {
+        let mut engine = AlertEngine::new(vec![rule(Some(80.0), 0, None)]);
+        let events = evaluate_all(
+            &mut engine,
+            &[capture(0, Some(95.0)), capture(1, Some(80.0))],
+        );
+        assert_eq!(events, vec![vec!["firing"], vec!["resolved"]]);
+        assert!(engine.active().is_empty());
+
+        // fires again once breached after resolving
+        let events = evaluate_all(&mut engine, &[capture(2, Some(95.0))]);
+        assert_eq!(events, vec![vec!["firing"]]);
+    }
+
+    #[test]
+    fn resolves_below_the_threshold_without_a_clear_threshold() {
+        let mut engine = AlertEngine::new(vec![rule(None, 0, None)]);
+        let events = evaluate_all(
+            &mut engine,
+            &[capture(0, Some(95.0)), capture(1, Some(90.0))],
+        );
+        assert_eq!(events, vec![vec!["firing"], vec!["resolved"]]);
+    }
+
+    #[test]
+    fn resolves_when_the_metric_is_gone() {
+        let mut engine = AlertEngine::new(vec![rule(Some(80.0), 0, None)]);
+        let events = evaluate_all(&mut engine, &[capture(0, Some(95.0)), capture(1, None)]);
+        assert_eq!(events, vec![vec!["firing"], vec!["resolved"]]);
+    }
+
+    #[test]
+    fn renotifies_while_firing() {
+        let mut engine = AlertEngine::new(vec![rule(None, 0, Some(300))]);
+        let events = evaluate_all(
+            &mut engine,
+            &[
+                capture(0, Some(95.0)),
+                capture(299, Some(95.0)),
+                capture(300, Some(95.0)),
+                capture(400, Some(95.0)),
+                capture(599, Some(95.0)),
+                capture(600, Some(95.0)),
+            ],
+        );
+        assert_eq!(
+            events,
+            vec![
+                vec!["firing"],
+                vec![],
+                vec!["firing"],
+                vec![],
+                vec![],
+                vec!["firing"]
+            ]
+        );
+        let alert = &engine.active()[0];
+        assert_eq!(alert.fired_at, Some(UNIX_EPOCH));
+        assert_eq!(
+            alert.notified_at,
+            Some(UNIX_EPOCH + Duration::from_secs(600))
+        );
+    }
+
+    #[test]
+    fn notifies_once_without_renotify() {
+        let mut engine = AlertEngine::new(vec![rule(None, 0, None)]);
+        let events = evaluate_all(
+            &mut engine,
+            &[
+                capture(0, Some(95.0)),
+                capture(3600, Some(95.0)),
+                capture(86400, Some(95.0)),
+            ],
+        );
+        assert_eq!(events, vec![vec!["firing"], vec![], vec![]]);
+    }
+}
@@ -0,0 +1,235 @@
+use agent_config::types::CgroupsConfig;
+use agent_core::metrics::{
+    CgroupCpuMetrics, CgroupIoMetrics, CgroupMemoryMetrics, CgroupMetrics, CgroupPidsMetrics,
+    Metrics,
+};
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::path::Path;
+
+use crate::Collector;
+
+const SELF_CGROUP_PATH: &str = "/proc/self/cgroup";
+
+/// Read a cgroup file, trimming the trailing newline
+fn read_file(dir: &Path, name: &str) -> Option<String> {
+    read_to_string(dir.join(name))
+        .ok()
+        .map(|v| v.trim().to_string())
+}
+
+/// Parse a single value file, where "max" means no limit
+fn read_limit(dir: &Path, name: &str) -> Option<Option<u64>> {
+    match read_file(dir, name)?.as_str() {
+        "max" => Some(None),
+        v => v.parse::<u64>().ok().map(Some),
+    }
+}
+
+/// Parse a flat keyed file (e.g. cpu.stat) into a map
+fn read_keyed(dir: &Path, name: &str) -> Option<HashMap<String, u64>> {
+    let raw = read_file(dir, name)?;
+    Some(
+        raw.lines()
+            .filter_map(|line| {
+                let (key, value) = line.split_once(' ')?;
+                Some((key.to_string(), value.trim().parse::<u64>().ok()?))
+            })
+            .collect(),
+    )
+}
+
+fn read_cpu(dir: &Path) -> Option<CgroupCpuMetrics> {
+    let stat = read_keyed(dir, "cpu.stat")?;
+    Some(CgroupCpuMetrics {
+        usage_usec: *stat.get("usage_usec")?,
+        user_usec: stat.get("user_usec").copied().unwrap_or_default(),
+        system_usec: stat.get("system_usec").copied().unwrap_or_default(),
+        nr_throttled: stat.get("nr_throttled").copied().unwrap_or_default(),
+        throttled_usec: stat.get("throttled_usec").copied().unwrap_or_default(),
+    })
+}
+
+fn read_memory(dir: &Path) -> Option<CgroupMemoryMetrics> {
+    let current = read_file(dir, "memory.current")?.parse::<u64>().ok()?;
+    let max = read_limit(dir, "memory.max").flatten();
+    Some(CgroupMemoryMetrics {
+        current,
+        max,
+        perc_used: max.map(|max| (current as f64 / max as f64 * 100.0) as f32),
+    })
+}
+
+/// Read io.stat, summing the counters of every device
+fn read_io(dir: &Path) -> Option<CgroupIoMetrics> {
+    let raw = read_file(dir, "io.stat")?;
+    let mut io = CgroupIoMetrics {
+        rbytes: 0,
+        wbytes: 0,
+        rios: 0,
+        wios: 0,
+    };
+    // each line looks like: '8:0 rbytes=1 wbytes=2 rios=3 wios=4 dbytes=0 dios=0'
+    for (key, value) in raw
+        .lines()
+        .flat_map(|line| line.split_whitespace().skip(1))
+        .filter_map(|field| field.split_once('='))
+    {
+        let value = value.parse::<u64>().unwrap_or_default();
+        match key {
+            "rbytes" => io.rbytes += value,
+            "wbytes" => io.wbytes += value,
+            "rios" => io.rios += value,
+            "wios" => io.wios += value,
+            _ => {}
+        }
+    }
+    Some(io)
+}
+
+fn read_pids(dir: &Path) -> Option<CgroupPidsMetrics> {
+    Some(CgroupPidsMetrics {
+        current: read_file(dir, "pids.current")?.parse::<u64>().ok()?,
+        max: read_limit(dir, "pids.max").flatten(),
+    })
+}
+
+/// Path of the cgroup the agent is running in, relative to the cgroup root
+fn self_cgroup() -> Option<String> {
+    // unified hierarchy entry looks like: '0::/system.slice/agent.service'
+    read_to_string(SELF_CGROUP_PATH)
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .map(|v| v.to_string())
+}
+
+/// Read the statistics of a cgroup, returning None if it does not exist
+fn read_cgroup(root: &Path, path: &str) -> Option<CgroupMetrics> {
+    let dir = root.join(path.trim_start_matches('/'));
+    if !dir.is_dir() {
+        return None;
+    }
+    Some(CgroupMetrics {
+        path: path.to_string(),
+        cpu: read_cpu(&dir),
+        memory: read_memory(&dir),
+        io: read_io(&dir),
+        pids: read_pids(&dir),
+    })
+}
+
+/// Collects cgroup v2 statistics, for the agent's own and configured cgroups
+pub(crate) struct CgroupsCollector {
+    config: CgroupsConfig,
+}
+
+impl CgroupsCollector {
+    pub fn new(config: CgroupsConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl Collector for CgroupsCollector {
+    fn name(&self) -> &str {
+        "cgroups"
+    }
+    fn collect(&self, metrics: &mut Metrics) {
+        let mut paths = vec![];
+        if self.config.include_self {
+            match self_cgroup() {
+                Some(v) => paths.push(v),
+                None => log::debug!("unable to find agent's own cgroup"),
+            }
+        }
+        paths.extend(self.config.paths.iter().cloned());
+
+        metrics.cgroups = Some(
+            paths
+                .iter()
+                .filter_map(|path| {
+                    let cgroup = read_cgroup(&self.config.root, path);
+                    if cgroup.is_none() {
+                        log::debug!("skipping cgroup '{path}' as it does not exist");
+                    }
+                    cgroup
+                })
+                .collect(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::test_fixture as fixture;
+
+    #[test]
+    fn read_cgroup_reads_every_controller() {
+        let root = fixture(&[
+            (
+                "system.slice/app.service/cpu.stat",
+                "usage_usec 2500000\nuser_usec 2000000\nsystem_usec 500000\n\
+                 nr_periods 10\nnr_throttled 3\nthrottled_usec 100000",
+            ),
+            ("system.slice/app.service/memory.current", "1048576"),
+            ("system.slice/app.service/memory.max", "4194304"),
+            (
+                "system.slice/app.service/io.stat",
+                "8:0 rbytes=100 wbytes=200 rios=1 wios=2 dbytes=0 dios=0\n\
+                 8:16 rbytes=50 wbytes=25 rios=3 wios=4 dbytes=0 dios=0",
+            ),
+            ("system.slice/app.service/pids.current", "4"),
+            ("system.slice/app.service/pids.max", "max"),
+        ]);
+        // paths from /proc/self/cgroup start with '/'
+        let cgroup = read_cgroup(root.path(), "/system.slice/app.service").unwrap();
+        assert_eq!(cgroup.path, "/system.slice/app.service");
+
+        let cpu = cgroup.cpu.unwrap();
+        assert_eq!(cpu.usage_usec, 2500000);
+        assert_eq!(cpu.user_usec, 2000000);
+        assert_eq!(cpu.system_usec, 500000);
+        assert_eq!(cpu.nr_throttled, 3);
+        assert_eq!(cpu.throttled_usec, 100000);
+
+        let memory = cgroup.memory.unwrap();
+        assert_eq!(memory.current, 1048576);
+        assert_eq!(memory.max, Some(4194304));
+        assert_eq!(memory.perc_used, Some(25.0));
+
+        // io is summed across devices
+        let io = cgroup.io.unwrap();
+        assert_eq!(io.rbytes, 150);
+        assert_eq!(io.wbytes, 225);
+        assert_eq!(io.rios, 4);
+        assert_eq!(io.wios, 6);
+
+        let pids = cgroup.pids.unwrap();
+        assert_eq!(pids.current, 4);
+        assert_eq!(pids.max, None);
+    }
+
+    #[test]
+    fn read_cgroup_without_limits_or_controllers() {
+        let root = fixture(&[
+            ("app/memory.current", "2048"),
+            ("app/memory.max", "max"),
+            // usage is required, other cpu counters default to 0
+            ("app/cpu.stat", "usage_usec 10"),
+        ]);
+        let cgroup = read_cgroup(root.path(), "app").unwrap();
+        let memory = cgroup.memory.unwrap();
+        assert_eq!(memory.max, None);
+        assert_eq!(memory.perc_used, None);
+        assert_eq!(cgroup.cpu.unwrap().nr_throttled, 0);
+        assert!(cgroup.io.is_none());
+        assert!(cgroup.pids.is_none());
+    }
+
+    #[test]
+    fn read_cgroup_missing_is_none() {
+        let root = fixture(&[("app/memory.current", "2048")]);
+        assert!(read_cgroup(root.path(), "other").is_none());
+    }
+}
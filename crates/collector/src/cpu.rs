@@ -0,0 +1,34 @@
+use agent_core::metrics::{CpuLoadMetrics, CpuMetrics, Metrics};
+use psutil::cpu::CpuPercentCollector;
+use std::sync::Mutex;
+
+use crate::Collector;
+
+/// Collects cpu load
+pub(crate) struct CpuCollector {
+    cpu_collector: Mutex<CpuPercentCollector>,
+}
+
+impl CpuCollector {
+    pub fn new() -> Self {
+        Self {
+            cpu_collector: Mutex::new(CpuPercentCollector::new().unwrap()),
+        }
+    }
+}
+
+impl Collector for CpuCollector {
+    fn name(&self) -> &str {
+        "cpu"
+    }
+    fn collect(&self, metrics: &mut Metrics) {
+        let mut cpu = self.cpu_collector.lock().unwrap();
+
+        metrics.cpu = Some(CpuMetrics {
+            load: Some(CpuLoadMetrics {
+                average: cpu.cpu_percent().unwrap(),
+                per_core: Some(cpu.cpu_percent_percpu().unwrap()),
+            }),
+        });
+    }
+}
@@ -0,0 +1,173 @@
+use agent_config::types::CommandConfig;
+use agent_core::metrics::{CustomCommandMetrics, CustomValue, Metrics};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::sync::{mpsc, Arc, Mutex, Once};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::helpers::parse_json;
+use crate::Collector;
+
+/// How often to check whether a command has exited
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Parse a value from a 'key value' line
+fn parse_value(raw: &str) -> CustomValue {
+    if let Ok(v) = raw.parse::<f64>() {
+        return CustomValue::Number(v);
+    }
+    match raw {
+        "true" => CustomValue::Bool(true),
+        "false" => CustomValue::Bool(false),
+        _ => CustomValue::Text(raw.to_string()),
+    }
+}
+
+/// Parse command output, either a JSON object or 'key value' lines
+fn parse_output(output: &str) -> Result<BTreeMap<String, CustomValue>, String> {
+    if output.trim_start().starts_with('{') {
+        return parse_json(output);
+    }
+    let mut values = BTreeMap::new();
+    for line in output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+    {
+        match line.split_once(char::is_whitespace) {
+            Some((key, value)) => values.insert(key.to_string(), parse_value(value.trim())),
+            None => return Err(format!("invalid line in output: '{line}'")),
+        };
+    }
+    Ok(values)
+}
+
+/// Run a command, killing it if it takes longer than its timeout
+fn run(command: &CommandConfig) -> Result<String, String> {
+    let mut child = Command::new(&command.path)
+        .args(&command.args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|err| format!("unable to start: {err}"))?;
+
+    // output is read on its own thread, so a full pipe cannot stall the command
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut output = String::new();
+        let result = stdout.read_to_string(&mut output).map(|_| output);
+        sender.send(result).ok();
+    });
+
+    let deadline = Instant::now() + Duration::from_secs(command.timeout);
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() >= deadline => {
+                child.kill().ok();
+                child.wait().ok();
+                return Err(format!("timed out after {}s", command.timeout));
+            }
+            Ok(None) => thread::sleep(POLL_INTERVAL),
+            Err(err) => return Err(format!("unable to wait for command: {err}")),
+        }
+    };
+    if !status.success() {
+        return Err(format!("exited with {status}"));
+    }
+    match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        Ok(Ok(output)) => Ok(output),
+        Ok(Err(err)) => Err(format!("unable to read output: {err}")),
+        Err(_) => Err(format!("timed out after {}s", command.timeout)),
+    }
+}
+
+/// Run a command and parse its output, failures are reported in the metrics
+fn capture(command: &CommandConfig) -> CustomCommandMetrics {
+    let captured_at = SystemTime::now();
+    match run(command).and_then(|output| parse_output(&output)) {
+        Ok(values) => CustomCommandMetrics {
+            captured_at,
+            values,
+            error: None,
+        },
+        Err(err) => {
+            log::warn!("custom command '{}' failed: {err}", command.name);
+            CustomCommandMetrics {
+                captured_at,
+                values: BTreeMap::new(),
+                error: Some(err),
+            }
+        }
+    }
+}
+
+/// Run a command every interval on its own thread, storing each result.
+/// Returns a sender which stops the thread once dropped
+fn spawn_worker(
+    command: CommandConfig,
+    results: Arc<Mutex<BTreeMap<String, CustomCommandMetrics>>>,
+) -> mpsc::Sender<()> {
+    let (stop, stopped) = mpsc::channel::<()>();
+    let interval = Duration::from_secs(command.interval.max(1));
+    let name = command.name.clone();
+    let spawned = thread::Builder::new()
+        .name(format!("custom-{name}"))
+        .spawn(move || loop {
+            let started = Instant::now();
+            let result = capture(&command);
+            results.lock().unwrap().insert(command.name.clone(), result);
+            match stopped.recv_timeout(interval.saturating_sub(started.elapsed())) {
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                // the collector has been dropped
+                _ => break,
+            }
+        });
+    if let Err(err) = spawned {
+        log::error!("unable to start custom command '{name}' due to '{err}'");
+    }
+    stop
+}
+
+/// Collects metrics from user defined commands.
+/// Commands run in the background, so a slow command never delays a capture
+pub(crate) struct CustomCollector {
+    commands: Vec<CommandConfig>,
+    /// Last result of each command, commands are left out until they first finish
+    results: Arc<Mutex<BTreeMap<String, CustomCommandMetrics>>>,
+    /// Threads are started on the first capture, so nothing runs if the collector is disabled
+    started: Once,
+    /// Stops the command threads when the collector is dropped
+    workers: Mutex<Vec<mpsc::Sender<()>>>,
+}
+
+impl CustomCollector {
+    pub fn new(commands: Vec<CommandConfig>) -> Self {
+        Self {
+            commands,
+            results: Arc::new(Mutex::new(BTreeMap::new())),
+            started: Once::new(),
+            workers: Mutex::new(vec![]),
+        }
+    }
+}
+
+impl Collector for CustomCollector {
+    fn name(&self) -> &str {
+        "custom"
+    }
+    fn collect(&self, metrics: &mut Metrics) {
+        self.started.call_once(|| {
+            *self.workers.lock().unwrap() = self
+                .commands
+                .iter()
+                .map(|command| spawn_worker(command.clone(), self.results.clone()))
+                .collect();
+        });
+        metrics.custom = Some(self.results.lock().unwrap().clone());
+    }
+}
@@ -0,0 +1,165 @@
+use agent_config::types::DiskIoConfig;
+use agent_core::metrics::{DiskIoMetrics, DiskIoRateMetrics, Metrics};
+use std::fs::read_to_string;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::helpers::{matches_pattern, rate, with_previous, CounterSnapshot, Counters};
+use crate::Collector;
+
+const DISKSTATS_PATH: &str = "/proc/diskstats";
+const BLOCK_CLASS_PATH: &str = "/sys/class/block";
+/// diskstats always counts in 512 byte sectors, regardless of the device
+const SECTOR_SIZE: u64 = 512;
+
+/// Raw counters for a single block device, as read from /proc/diskstats
+#[derive(Debug, Clone)]
+struct DeviceCounters {
+    name: String,
+    reads: u64,
+    sectors_read: u64,
+    ms_reading: u64,
+    writes: u64,
+    sectors_written: u64,
+    ms_writing: u64,
+    ms_doing_io: u64,
+    weighted_ms_doing_io: u64,
+}
+
+impl Counters for DeviceCounters {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Parse the contents of /proc/diskstats into per-device counters
+fn parse_diskstats(raw: &str) -> Vec<DeviceCounters> {
+    raw.lines()
+        .filter_map(|line| {
+            let fields = line.split_whitespace().collect::<Vec<&str>>();
+            if fields.len() < 14 {
+                return None;
+            }
+            let values = fields[3..14]
+                .iter()
+                .map(|v| v.parse::<u64>().ok())
+                .collect::<Option<Vec<u64>>>()?;
+            Some(DeviceCounters {
+                name: fields[2].to_string(),
+                reads: values[0],
+                sectors_read: values[2],
+                ms_reading: values[3],
+                writes: values[4],
+                sectors_written: values[6],
+                ms_writing: values[7],
+                ms_doing_io: values[9],
+                weighted_ms_doing_io: values[10],
+            })
+        })
+        .collect()
+}
+
+/// Read the current counters of every block device
+fn read_counters() -> std::io::Result<Vec<DeviceCounters>> {
+    Ok(parse_diskstats(&read_to_string(DISKSTATS_PATH)?))
+}
+
+/// Whether the block device is a partition of another device
+fn is_partition(name: &str) -> bool {
+    Path::new(BLOCK_CLASS_PATH)
+        .join(name)
+        .join("partition")
+        .exists()
+}
+
+/// Whether the block device is a loop device
+fn is_loop(name: &str) -> bool {
+    name.starts_with("loop")
+}
+
+/// Convert a device's counters into metrics, calculating rates from the previous counters
+fn to_metrics(current: &DeviceCounters, previous: Option<(&DeviceCounters, f64)>) -> DiskIoMetrics {
+    let rates = previous.map(|(last, elapsed)| {
+        let reads = current.reads.saturating_sub(last.reads);
+        let writes = current.writes.saturating_sub(last.writes);
+        let elapsed_ms = elapsed * 1000.0;
+        // average time each request took, including time queued
+        let await_ms = |ms: u64, requests: u64| match requests {
+            0 => 0.0,
+            _ => ms as f64 / requests as f64,
+        };
+        DiskIoRateMetrics {
+            reads: rate(current.reads, last.reads, elapsed),
+            writes: rate(current.writes, last.writes, elapsed),
+            read_bytes: rate(current.sectors_read, last.sectors_read, elapsed) * SECTOR_SIZE as f64,
+            write_bytes: rate(current.sectors_written, last.sectors_written, elapsed)
+                * SECTOR_SIZE as f64,
+            read_await_ms: await_ms(current.ms_reading.saturating_sub(last.ms_reading), reads),
+            write_await_ms: await_ms(current.ms_writing.saturating_sub(last.ms_writing), writes),
+            queue_depth: rate(
+                current.weighted_ms_doing_io,
+                last.weighted_ms_doing_io,
+                elapsed_ms,
+            ),
+            perc_util: (rate(current.ms_doing_io, last.ms_doing_io, elapsed_ms) * 100.0).min(100.0)
+                as f32,
+        }
+    });
+    DiskIoMetrics {
+        device: current.name.clone(),
+        reads: current.reads,
+        writes: current.writes,
+        read_bytes: current.sectors_read * SECTOR_SIZE,
+        write_bytes: current.sectors_written * SECTOR_SIZE,
+        rates,
+    }
+}
+
+/// Collects block device io statistics
+pub(crate) struct DiskIoCollector {
+    config: DiskIoConfig,
+    snapshot: Mutex<Option<CounterSnapshot<DeviceCounters>>>,
+}
+
+impl DiskIoCollector {
+    pub fn new(config: DiskIoConfig) -> Self {
+        Self {
+            config,
+            snapshot: Mutex::new(None),
+        }
+    }
+    /// Whether the device has been excluded in config
+    fn is_excluded(&self, name: &str) -> bool {
+        self.config.exclude_loop && is_loop(name)
+            || self.config.exclude_partitions && is_partition(name)
+            || self
+                .config
+                .exclude
+                .iter()
+                .any(|pattern| matches_pattern(pattern, name))
+    }
+}
+
+impl Collector for DiskIoCollector {
+    fn name(&self) -> &str {
+        "disk_io"
+    }
+    fn collect(&self, metrics: &mut Metrics) {
+        let counters = match read_counters() {
+            Ok(v) => v,
+            Err(err) => {
+                log::error!("unable to read disk io stats due to '{err}'");
+                return;
+            }
+        };
+        let counters = counters
+            .into_iter()
+            .filter(|counter| !self.is_excluded(&counter.name))
+            .collect();
+
+        let mut snapshot = self.snapshot.lock().unwrap();
+        let (disk_io, new_snapshot) = with_previous(counters, snapshot.as_ref(), to_metrics);
+        *snapshot = Some(new_snapshot);
+        metrics.disk_io = Some(disk_io);
+    }
+}
@@ -0,0 +1,72 @@
+use agent_core::metrics::{DiskInodeMetrics, DiskMetrics, Metrics};
+use nix::sys::statvfs::statvfs;
+use psutil::disk::{disk_usage, partitions};
+
+use crate::Collector;
+
+/// Collects usage of every mounted filesystem
+pub(crate) struct DisksCollector;
+
+impl DisksCollector {
+    /// Gather & return usage of every mounted filesystem
+    fn get_disk_metrics(&self) -> Vec<DiskMetrics> {
+        let partitions = match partitions() {
+            Ok(v) => v,
+            Err(err) => {
+                log::error!("unable to read mounted filesystems due to '{err}'");
+                return vec![];
+            }
+        };
+
+        partitions
+            .iter()
+            .filter_map(|partition| {
+                let mount_point = partition.mountpoint();
+                let usage = match disk_usage(mount_point) {
+                    Ok(v) => v,
+                    Err(err) => {
+                        log::debug!("skipping disk usage for '{mount_point:?}' due to '{err}'");
+                        return None;
+                    }
+                };
+                // pseudo filesystems (proc, sysfs, etc) have no capacity
+                if usage.total() == 0 {
+                    return None;
+                }
+                let inodes = statvfs(mount_point).ok().and_then(|stat| {
+                    let total = stat.files();
+                    let free = stat.files_free();
+                    // some filesystems (e.g. btrfs) do not track inodes
+                    if total == 0 {
+                        return None;
+                    }
+                    Some(DiskInodeMetrics {
+                        total,
+                        used: total - free,
+                        free,
+                        perc_used: ((total - free) as f64 / total as f64 * 100.0) as f32,
+                    })
+                });
+                Some(DiskMetrics {
+                    mount_point: mount_point.to_string_lossy().to_string(),
+                    device: partition.device().to_string(),
+                    fs_type: partition.filesystem().as_str().to_string(),
+                    total: usage.total(),
+                    used: usage.used(),
+                    free: usage.free(),
+                    perc_used: usage.percent(),
+                    inodes,
+                })
+            })
+            .collect()
+    }
+}
+
+impl Collector for DisksCollector {
+    fn name(&self) -> &str {
+        "disks"
+    }
+    fn collect(&self, metrics: &mut Metrics) {
+        metrics.disks = Some(self.get_disk_metrics());
+    }
+}
@@ -0,0 +1,229 @@
+use agent_core::metrics::CustomValue;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Instant;
+
+/// Fields used to identify items in a list, instead of their position.
+/// Processes are identified by pid first, as several can share a name (e.g. nginx workers)
+const ID_KEYS: [&str; 7] = [
+    "pid",
+    "name",
+    "mount_point",
+    "device",
+    "path",
+    "file",
+    "label",
+];
+
+/// Check whether a name matches a pattern, where '*' matches any characters
+pub(crate) fn matches_pattern(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => {
+            let name = match name.strip_prefix(prefix) {
+                Some(v) => v,
+                None => return false,
+            };
+            // try the remaining pattern against every possible suffix
+            (0..=name.len())
+                .filter(|i| name.is_char_boundary(*i))
+                .any(|i| matches_pattern(rest, &name[i..]))
+        }
+    }
+}
+
+/// Flatten a JSON value into the values map, nested keys are joined with '.'
+fn flatten_json(prefix: &str, value: &Value, values: &mut BTreeMap<String, CustomValue>) {
+    let value = match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                let key = match prefix.is_empty() {
+                    true => key.to_string(),
+                    false => format!("{prefix}.{key}"),
+                };
+                flatten_json(&key, value, values);
+            }
+            return;
+        }
+        Value::Number(v) => match v.as_f64() {
+            Some(v) => CustomValue::Number(v),
+            None => return,
+        },
+        Value::Bool(v) => CustomValue::Bool(*v),
+        Value::String(v) => CustomValue::Text(v.to_string()),
+        // arrays and nulls have no single value to report
+        Value::Array(_) | Value::Null => return,
+    };
+    values.insert(prefix.to_string(), value);
+}
+
+/// Parse a JSON object into flat values
+pub(crate) fn parse_json(raw: &str) -> Result<BTreeMap<String, CustomValue>, String> {
+    let json: Value = serde_json::from_str(raw).map_err(|err| format!("invalid json: {err}"))?;
+    if !json.is_object() {
+        return Err("invalid json: expected an object".to_string());
+    }
+    let mut values = BTreeMap::new();
+    flatten_json("", &json, &mut values);
+    Ok(values)
+}
+
+/// Collect every numeric value by its path in the metrics, booleans are counted as 0 or 1
+pub(crate) fn numeric_values(prefix: &str, value: &Value, values: &mut BTreeMap<String, f64>) {
+    let join = |key: &str| match prefix.is_empty() {
+        true => key.to_string(),
+        false => format!("{prefix}.{key}"),
+    };
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                numeric_values(&join(key), value, values);
+            }
+        }
+        Value::Array(items) => {
+            let mut seen = HashSet::new();
+            for (i, item) in items.iter().enumerate() {
+                let key = ID_KEYS
+                    .iter()
+                    .find_map(|key| match item.get(key)? {
+                        Value::String(v) => Some(v.to_string()),
+                        Value::Number(v) => Some(v.to_string()),
+                        _ => None,
+                    })
+                    .unwrap_or_else(|| i.to_string());
+                // items sharing an id (e.g. two 'acpitz' sensors) are told apart by position
+                let key = match seen.insert(key.clone()) {
+                    true => key,
+                    false => format!("{key}#{i}"),
+                };
+                numeric_values(&join(&key), item, values);
+            }
+        }
+        Value::Number(v) => {
+            if let Some(v) = v.as_f64() {
+                values.insert(prefix.to_string(), v);
+            }
+        }
+        Value::Bool(v) => {
+            values.insert(prefix.to_string(), *v as u8 as f64);
+        }
+        Value::String(_) | Value::Null => {}
+    }
+}
+
+/// Raw counters read for a single named item (e.g. an interface or block device)
+pub(crate) trait Counters {
+    fn name(&self) -> &str;
+}
+
+/// Counters from a previous capture, used to calculate rates
+pub(crate) struct CounterSnapshot<T> {
+    taken_at: Instant,
+    counters: HashMap<String, T>,
+}
+
+/// Per second rate between two counter values, handling counter resets
+pub(crate) fn rate(current: u64, previous: u64, elapsed: f64) -> f64 {
+    current.saturating_sub(previous) as f64 / elapsed
+}
+
+/// Convert counters into metrics, passing each item its counters from the previous snapshot
+/// along with the seconds elapsed since, if any. Returns the metrics along with a snapshot to use for the next capture.
+pub(crate) fn with_previous<T: Counters, M>(
+    counters: Vec<T>,
+    previous: Option<&CounterSnapshot<T>>,
+    to_metrics: impl Fn(&T, Option<(&T, f64)>) -> M,
+) -> (Vec<M>, CounterSnapshot<T>) {
+    let now = Instant::now();
+    let metrics = counters
+        .iter()
+        .map(|current| {
+            let last = previous.and_then(|previous| {
+                let elapsed = now.duration_since(previous.taken_at).as_secs_f64();
+                let last = previous.counters.get(current.name())?;
+                if elapsed <= 0.0 {
+                    return None;
+                }
+                Some((last, elapsed))
+            });
+            to_metrics(current, last)
+        })
+        .collect();
+    let snapshot = CounterSnapshot {
+        taken_at: now,
+        counters: counters
+            .into_iter()
+            .map(|counter| (counter.name().to_string(), counter))
+            .collect(),
+    };
+    (metrics, snapshot)
+}
+
+/// Write files into a fixture directory (e.g. a fake sysfs or cgroup hierarchy),
+/// creating their parent directories
+#[cfg(test)]
+pub(crate) fn test_fixture(files: &[(&str, &str)]) -> tempfile::TempDir {
+    let root = tempfile::TempDir::new().unwrap();
+    for (path, contents) in files {
+        let path = root.path().join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, format!("{contents}\n")).unwrap();
+    }
+    root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn values_of(value: Value) -> Vec<String> {
+        let mut values = BTreeMap::new();
+        numeric_values("", &value, &mut values);
+        values.into_keys().collect()
+    }
+
+    #[test]
+    fn list_items_are_keyed_by_id() {
+        let disks = json!({"disks": [
+            {"mount_point": "/", "free": 1},
+            {"mount_point": "/boot", "free": 2},
+        ]});
+        assert_eq!(values_of(disks), vec!["disks./.free", "disks./boot.free"]);
+    }
+
+    #[test]
+    fn list_items_without_id_are_keyed_by_position() {
+        let cores = json!({"per_core": [{"busy": 1}, {"busy": 2}]});
+        assert_eq!(values_of(cores), vec!["per_core.0.busy", "per_core.1.busy"]);
+    }
+
+    #[test]
+    fn processes_are_keyed_by_pid() {
+        let top = json!({"top_cpu": [
+            {"pid": 10, "name": "nginx", "cpu_perc": 5.0},
+            {"pid": 11, "name": "nginx", "cpu_perc": 4.0},
+        ]});
+        assert_eq!(
+            values_of(top),
+            vec![
+                "top_cpu.10.cpu_perc",
+                "top_cpu.10.pid",
+                "top_cpu.11.cpu_perc",
+                "top_cpu.11.pid"
+            ]
+        );
+    }
+
+    #[test]
+    fn list_items_sharing_an_id_are_kept_apart() {
+        let sensors = json!({"sensors": [
+            {"label": "acpitz", "current": 40},
+            {"label": "acpitz", "current": 50},
+        ]});
+        assert_eq!(
+            values_of(sensors),
+            vec!["sensors.acpitz#1.current", "sensors.acpitz.current"]
+        );
+    }
+}
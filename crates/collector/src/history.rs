@@ -0,0 +1,127 @@
+use agent_config::types::HistoryConfig;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::time::SystemTime;
+
+/// Bounded buffer of the most recent captures
+pub(crate) struct History {
+    config: HistoryConfig,
+    /// Serialised captures, oldest first, with when they were taken.
+    /// Kept as text as it is several times smaller than a parsed [Value]
+    captures: VecDeque<(SystemTime, Box<str>)>,
+    total_bytes: usize,
+}
+
+impl History {
+    pub fn new(config: HistoryConfig) -> Self {
+        Self {
+            captures: VecDeque::with_capacity(config.length),
+            config,
+            total_bytes: 0,
+        }
+    }
+    pub fn is_enabled(&self) -> bool {
+        self.config.length > 0
+    }
+    /// Add a serialised capture, dropping the oldest when over the length or memory budget
+    pub fn push(&mut self, captured_at: SystemTime, captured: String) {
+        if !self.is_enabled() {
+            return;
+        }
+        let captured = captured.into_boxed_str();
+        self.total_bytes += captured.len();
+        self.captures.push_back((captured_at, captured));
+        // the newest capture is always kept
+        while self.captures.len() > 1
+            && (self.captures.len() > self.config.length
+                || self.total_bytes > self.config.max_bytes)
+        {
+            if let Some((_, captured)) = self.captures.pop_front() {
+                self.total_bytes -= captured.len();
+            }
+        }
+    }
+    /// Captures taken after the given time, oldest first
+    pub fn since(&self, since: Option<SystemTime>) -> Vec<Value> {
+        self.captures
+            .iter()
+            .filter(|(captured_at, _)| match since {
+                Some(since) => *captured_at > since,
+                None => true,
+            })
+            .map(|(_, captured)| {
+                serde_json::from_str(captured).expect("serialised metrics can be parsed")
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn capture(n: usize) -> String {
+        // single digits, so every capture is the same size
+        format!(r#"{{"n":{n}}}"#)
+    }
+
+    fn history(length: usize, max_bytes: usize) -> History {
+        History::new(HistoryConfig { length, max_bytes })
+    }
+
+    fn numbers(history: &History, since: Option<SystemTime>) -> Vec<u64> {
+        history
+            .since(since)
+            .iter()
+            .map(|v| v["n"].as_u64().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn oldest_dropped_past_length() {
+        let mut history = history(3, usize::MAX);
+        for n in 0..5 {
+            history.push(SystemTime::now(), capture(n));
+        }
+        assert_eq!(numbers(&history, None), vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn oldest_dropped_past_memory_budget() {
+        let size = capture(0).len();
+        let mut history = history(100, size * 3 + size / 2);
+        for n in 0..10 {
+            history.push(SystemTime::now(), capture(n));
+        }
+        assert_eq!(numbers(&history, None), vec![7, 8, 9]);
+        assert_eq!(history.total_bytes, size * 3);
+    }
+
+    #[test]
+    fn newest_kept_over_memory_budget() {
+        let mut history = history(100, 1);
+        history.push(SystemTime::now(), capture(0));
+        history.push(SystemTime::now(), capture(1));
+        assert_eq!(numbers(&history, None), vec![1]);
+    }
+
+    #[test]
+    fn disabled_keeps_nothing() {
+        let mut history = history(0, usize::MAX);
+        history.push(SystemTime::now(), capture(0));
+        assert!(numbers(&history, None).is_empty());
+        assert_eq!(history.total_bytes, 0);
+    }
+
+    #[test]
+    fn since_only_returns_newer() {
+        let mut history = history(10, usize::MAX);
+        let start = SystemTime::now();
+        for n in 0..4 {
+            history.push(start + Duration::from_secs(n), capture(n as usize));
+        }
+        let since = Some(start + Duration::from_secs(1));
+        assert_eq!(numbers(&history, since), vec![2, 3]);
+    }
+}
@@ -1,105 +1,315 @@
-use agent_core::metrics::{
-    CapturedMetrics, CpuLoadMetrics, CpuMetrics, MemoryDetailedMetrics, MemoryMetrics, Metrics,
-};
-use psutil::cpu::CpuPercentCollector;
-use std::sync::{Mutex, RwLock};
-use std::time::Duration;
+use agent_config::types::{CollectorsConfig, Config, COLLECTOR_NAMES};
+use agent_core::alerts::{Alert, AlertEvent};
+use agent_core::metrics::{CapturedMetrics, Metrics};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{broadcast, watch};
+use tokio::task;
+use tokio::time::{self, MissedTickBehavior};
+
+mod alerts;
+mod cgroups;
+mod cpu;
+mod custom;
+mod disk_io;
+mod disks;
+mod helpers;
+mod history;
+mod memory;
+mod network;
+mod pressure;
+mod processes;
+mod sensors;
+mod store;
+mod system;
+mod textfile;
+mod watched;
+
+pub use store::StoreError;
+
+/// Alert events kept for receivers that have fallen behind
+const ALERT_EVENTS_CAPACITY: usize = 64;
+
+/// A source of metrics, such as cpu or memory.
+/// Collectors add their own section to the captured metrics.
+pub trait Collector: Send + Sync {
+    /// Unique name of the collector, used to enable/disable it in config
+    fn name(&self) -> &str;
+    /// Gather metrics, adding them into the given metrics
+    fn collect(&self, metrics: &mut Metrics);
+}
 
 /// Manages gathering metrics
 pub struct CollectorState {
     cache_for: Duration,
-    metrics: RwLock<Option<CapturedMetrics>>,
-    cpu_collector: Mutex<CpuPercentCollector>,
+    /// Whether metrics are captured by a background task, instead of on request
+    sampling: bool,
+    collectors_config: CollectorsConfig,
+    collectors: RwLock<Vec<Box<dyn Collector>>>,
+    /// Prevents concurrent requests from capturing at the same time
+    capture_lock: Mutex<()>,
+    /// Latest captured metrics
+    metrics: watch::Sender<Option<CapturedMetrics>>,
+    history: Mutex<history::History>,
+    store: Option<store::Store>,
+    alerts: Mutex<alerts::AlertEngine>,
+    alert_events: broadcast::Sender<AlertEvent>,
 }
 
 impl CollectorState {
-    pub fn new(cache_for: Duration) -> Self {
-        log::debug!("Captured metrics will cache for '{cache_for:?}'");
-        Self {
+    pub fn new(config: &Config) -> Self {
+        let cache_for = Duration::from_secs(config.cache_for);
+        match (config.sample_interval, config.sampling_interval()) {
+            (Some(v), _) => log::debug!("metrics will be captured every '{v}' seconds"),
+            (None, Some(v)) => log::info!(
+                "sample_interval not set, metrics will be captured every '{v}' seconds so alerts are checked"
+            ),
+            (None, None) => log::debug!("Captured metrics will cache for '{cache_for:?}'"),
+        };
+        for name in config.collectors.unknown() {
+            log::warn!("ignoring unknown collector '{name}' in config");
+        }
+        let (metrics, _) = watch::channel(None);
+        let state = Self {
             cache_for,
-            metrics: RwLock::new(None),
-            cpu_collector: Mutex::new(CpuPercentCollector::new().unwrap()),
+            sampling: config.sampling_interval().is_some(),
+            collectors_config: config.collectors.clone(),
+            collectors: RwLock::new(vec![]),
+            capture_lock: Mutex::new(()),
+            metrics,
+            history: Mutex::new(history::History::new(config.history.clone())),
+            store: config
+                .store
+                .path
+                .as_ref()
+                .map(|path| store::Store::new(path, &config.store)),
+            alerts: Mutex::new(alerts::AlertEngine::new(config.alerts.clone())),
+            alert_events: broadcast::channel(ALERT_EVENTS_CAPACITY).0,
+        };
+        state.register(Box::new(cpu::CpuCollector::new()));
+        state.register(Box::new(memory::MemoryCollector::new()));
+        state.register(Box::new(disks::DisksCollector));
+        state.register(Box::new(disk_io::DiskIoCollector::new(
+            config.disk_io.clone(),
+        )));
+        state.register(Box::new(network::NetworkCollector::new(
+            config.network.exclude.clone(),
+        )));
+        state.register(Box::new(system::SystemCollector));
+        state.register(Box::new(sensors::SensorsCollector::new(
+            config.sensors.sysfs_root.clone(),
+        )));
+        // processes are read once per capture, for both the process counts & watched processes
+        let process_table = processes::ProcessTable::shared();
+        state.register(Box::new(processes::ProcessesCollector::new(
+            config.processes.clone(),
+            process_table.clone(),
+        )));
+        state.register(Box::new(watched::WatchedCollector::new(
+            &config.watched,
+            process_table,
+        )));
+        state.register(Box::new(cgroups::CgroupsCollector::new(
+            config.cgroups.clone(),
+        )));
+        state.register(Box::new(pressure::PressureCollector));
+        state.register(Box::new(custom::CustomCollector::new(
+            config.commands.clone(),
+        )));
+        state.register(Box::new(textfile::TextfileCollector::new(
+            config.textfile.clone(),
+        )));
+        state
+    }
+    /// Add a collector, it will be ignored if disabled in config
+    pub fn register(&self, collector: Box<dyn Collector>) {
+        let name = collector.name();
+        debug_assert!(
+            COLLECTOR_NAMES.contains(&name),
+            "collector '{name}' is missing from COLLECTOR_NAMES"
+        );
+        if !self.collectors_config.is_enabled(name) {
+            log::info!("collector '{name}' is disabled");
+            return;
         }
+        log::debug!("registered collector '{name}'");
+        self.collectors
+            .write()
+            .expect("cannot gain write lock on collectors")
+            .push(collector);
     }
-    /// Gather & return cpu metrics
-    fn get_cpu_metrics(&self) -> CpuMetrics {
-        let mut cpu = self.cpu_collector.lock().unwrap();
-
-        CpuMetrics {
-            load: Some(CpuLoadMetrics {
-                average: cpu.cpu_percent().unwrap(),
-                per_core: Some(cpu.cpu_percent_percpu().unwrap()),
-            }),
+    /// Return new metrics, skipping cache
+    pub fn metrics_skip_cache(&self) -> CapturedMetrics {
+        let mut metrics = Metrics::default();
+        for collector in self
+            .collectors
+            .read()
+            .expect("cannot gain read lock on collectors")
+            .iter()
+        {
+            collector.collect(&mut metrics);
         }
+        CapturedMetrics::new_from_now(metrics)
     }
-    /// Gather & return memory metrics
-    fn get_memory_metrics(&self) -> MemoryMetrics {
-        let memory = psutil::memory::virtual_memory().unwrap();
+    /// Capture new metrics, publishing them as the latest
+    fn capture(&self) -> CapturedMetrics {
+        let new_metrics = self.metrics_skip_cache();
+        self.metrics.send_replace(Some(new_metrics.clone()));
 
-        MemoryMetrics {
-            perc_used: memory.percent(),
-            detailed: Some(MemoryDetailedMetrics {
-                total: memory.total(),
-                available: memory.available(),
-                used: memory.used(),
-                free: memory.free(),
-            }),
+        // serialised once for the history, alerts & store,
+        // values are parsed from the text so f32 values are not widened (e.g. 10.4 to 10.399999618530273)
+        let record = serde_json::to_string(&new_metrics).expect("metrics can be serialised");
+        let parsed: serde_json::Value =
+            serde_json::from_str(&record).expect("serialised metrics can be parsed");
+        let mut values = BTreeMap::new();
+        helpers::numeric_values("", &parsed["metrics"], &mut values);
+
+        let events = self
+            .alerts
+            .lock()
+            .expect("cannot gain lock on alert engine")
+            .evaluate(&values, new_metrics.captured_at);
+        for event in events {
+            // no receivers is not an error, as webhooks may be disabled
+            self.alert_events.send(event).ok();
+        }
+        if let Some(store) = &self.store {
+            let time = new_metrics
+                .captured_at
+                .duration_since(UNIX_EPOCH)
+                .map(|v| v.as_secs())
+                .unwrap_or_default();
+            if let Err(err) = store.record(time, &record, &values) {
+                log::error!("unable to store metrics due to '{err}'");
+            }
         }
+        self.history
+            .lock()
+            .expect("cannot gain lock on metrics history")
+            .push(new_metrics.captured_at, record);
+        log::debug!("captured new metrics");
+        new_metrics
     }
-    /// Return new metrics, skipping cache
-    pub fn metrics_skip_cache(&self) -> CapturedMetrics {
-        CapturedMetrics::new_from_now(Metrics {
-            cpu: self.get_cpu_metrics(),
-            memory: self.get_memory_metrics(),
-        })
+    /// Write out anything only kept in memory, such as unfinished rollups.
+    /// Called once the agent is shutting down
+    pub fn flush(&self) {
+        let _guard = self
+            .capture_lock
+            .lock()
+            .expect("cannot gain lock on metrics capture");
+        if let Some(store) = &self.store {
+            if let Err(err) = store.flush() {
+                log::error!("unable to store unfinished rollups due to '{err}'");
+            }
+        }
     }
-    /// Return metrics, using cached if valid
-    pub fn metrics(&self) -> CapturedMetrics {
-        let mut metrics_to_return: Option<CapturedMetrics> = None;
-
-        // get existing metrics from cache, if they are still valid
-        {
-            let metrics_cache = self
-                .metrics
-                .read()
-                .expect("cannot gain read lock on metrics cache");
-            match &*metrics_cache {
-                Some(v) => match v.is_old(self.cache_for) {
-                    true => {
-                        log::debug!("metrics capture needed, cache old");
-                    }
-                    false => {
-                        log::debug!("metrics capture skipped, using cached");
-                        metrics_to_return = Some(v.clone());
-                    }
-                },
-                None => {
-                    log::debug!("metrics capture needed, none in cache");
+    /// Return the latest metrics, if they are still valid
+    fn cached(&self) -> Option<CapturedMetrics> {
+        let metrics = self.metrics.borrow();
+        match &*metrics {
+            // sampled metrics are always the latest available
+            Some(v) if self.sampling => Some(v.clone()),
+            Some(v) => match v.is_old(self.cache_for) {
+                true => {
+                    log::debug!("metrics capture needed, cache old");
+                    None
+                }
+                false => {
+                    log::debug!("metrics capture skipped, using cached");
+                    Some(v.clone())
                 }
-            };
+            },
+            None => {
+                log::debug!("metrics capture needed, none in cache");
+                None
+            }
         }
-
-        // update cached metrics
-        if metrics_to_return.is_none() {
-            let mut metrics_cache = self
-                .metrics
-                .try_write()
-                .expect("cannot gain write lock on metrics cache");
-            let new_metrics = self.metrics_skip_cache();
-            *metrics_cache = Some(new_metrics);
-            log::debug!("captured new metrics in cache");
+    }
+    /// Return metrics, using cached if valid.
+    /// When sampling, this only captures if no sample has been taken yet
+    pub fn metrics(&self) -> CapturedMetrics {
+        if let Some(v) = self.cached() {
+            return v;
         }
-
-        // either used previously cached value or the newly cached metrics
-        match metrics_to_return {
-            Some(v) => v,
-            None => self
-                .metrics
-                .read()
-                .expect("cannot gain read lock on metrics cache")
-                .as_ref()
-                .expect("metrics cache cannot be None")
-                .clone(),
+        let _guard = self
+            .capture_lock
+            .lock()
+            .expect("cannot gain lock on metrics capture");
+        // another request may have captured while waiting for the lock
+        if let Some(v) = self.cached() {
+            return v;
+        }
+        self.capture()
+    }
+    /// Return metrics as [CollectorState::metrics] does, for async code.
+    /// Captures are made on a blocking thread, so slow collectors don't stall the async workers
+    pub async fn metrics_async(state: Arc<Self>) -> CapturedMetrics {
+        if let Some(v) = state.cached() {
+            return v;
+        }
+        task::spawn_blocking(move || state.metrics())
+            .await
+            .expect("metrics capture panicked")
+    }
+    /// Return previous captures taken after the given time, oldest first.
+    /// None when history is disabled
+    pub fn history(&self, since: Option<SystemTime>) -> Option<Vec<serde_json::Value>> {
+        let history = self
+            .history
+            .lock()
+            .expect("cannot gain lock on metrics history");
+        match history.is_enabled() {
+            true => Some(history.since(since)),
+            false => None,
+        }
+    }
+    /// Read stored captures, or rollups of the given interval, between the given unix times.
+    /// None when the store is disabled
+    pub fn stored(
+        &self,
+        interval: Option<u64>,
+        from: u64,
+        to: u64,
+    ) -> Option<Result<Vec<serde_json::Value>, StoreError>> {
+        self.store
+            .as_ref()
+            .map(|store| store.query(interval, from, to))
+    }
+    /// Receive metrics each time they are captured
+    pub fn subscribe(&self) -> watch::Receiver<Option<CapturedMetrics>> {
+        self.metrics.subscribe()
+    }
+    /// Pending & firing alerts
+    pub fn alerts(&self) -> Vec<Alert> {
+        self.alerts
+            .lock()
+            .expect("cannot gain lock on alert engine")
+            .active()
+    }
+    /// Receive alerts as they fire or resolve
+    pub fn subscribe_alerts(&self) -> broadcast::Receiver<AlertEvent> {
+        self.alert_events.subscribe()
+    }
+    /// Capture metrics at a fixed interval, forever
+    pub async fn run_sampler(state: Arc<Self>, interval: Duration) {
+        let mut interval = time::interval(interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let state = state.clone();
+            // collectors block while reading, so they are kept off the async workers
+            let result = task::spawn_blocking(move || {
+                let _guard = state
+                    .capture_lock
+                    .lock()
+                    .expect("cannot gain lock on metrics capture");
+                state.capture();
+            })
+            .await;
+            if let Err(err) = result {
+                log::error!("metrics capture failed: {err}");
+            }
         }
     }
 }
@@ -0,0 +1,93 @@
+use agent_core::metrics::{
+    MemoryDetailedMetrics, MemoryMetrics, Metrics, SwapMetrics, SwapRateMetrics,
+};
+use nix::unistd::{sysconf, SysconfVar};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::Collector;
+
+/// Swap counters from a previous capture, used to calculate rates
+struct SwapSnapshot {
+    taken_at: Instant,
+    swapped_in: u64,
+    swapped_out: u64,
+}
+
+/// Collects memory and swap usage
+pub(crate) struct MemoryCollector {
+    swap_snapshot: Mutex<Option<SwapSnapshot>>,
+}
+
+impl MemoryCollector {
+    pub fn new() -> Self {
+        Self {
+            swap_snapshot: Mutex::new(None),
+        }
+    }
+    /// Gather & return swap metrics
+    fn get_swap_metrics(&self) -> Option<SwapMetrics> {
+        let swap = match psutil::memory::swap_memory() {
+            Ok(v) => v,
+            Err(err) => {
+                log::error!("unable to read swap memory due to '{err}'");
+                return None;
+            }
+        };
+        // swap activity is counted in pages
+        let page_size = match sysconf(SysconfVar::PAGE_SIZE) {
+            Ok(Some(v)) => v as u64,
+            _ => 4096,
+        };
+        let swapped_in = swap.swapped_in() * page_size;
+        let swapped_out = swap.swapped_out() * page_size;
+
+        let now = Instant::now();
+        let mut snapshot = self.swap_snapshot.lock().unwrap();
+        let rates = snapshot.as_ref().and_then(|previous| {
+            let elapsed = now.duration_since(previous.taken_at).as_secs_f64();
+            if elapsed <= 0.0 {
+                return None;
+            }
+            Some(SwapRateMetrics {
+                swapped_in: swapped_in.saturating_sub(previous.swapped_in) as f64 / elapsed,
+                swapped_out: swapped_out.saturating_sub(previous.swapped_out) as f64 / elapsed,
+            })
+        });
+        *snapshot = Some(SwapSnapshot {
+            taken_at: now,
+            swapped_in,
+            swapped_out,
+        });
+
+        Some(SwapMetrics {
+            perc_used: swap.percent(),
+            total: swap.total(),
+            used: swap.used(),
+            free: swap.free(),
+            swapped_in,
+            swapped_out,
+            rates,
+        })
+    }
+}
+
+impl Collector for MemoryCollector {
+    fn name(&self) -> &str {
+        "memory"
+    }
+    fn collect(&self, metrics: &mut Metrics) {
+        let memory = psutil::memory::virtual_memory().unwrap();
+
+        metrics.memory = Some(MemoryMetrics {
+            perc_used: memory.percent(),
+            detailed: Some(MemoryDetailedMetrics {
+                total: memory.total(),
+                available: memory.available(),
+                used: memory.used(),
+                free: memory.free(),
+            }),
+            swap: self.get_swap_metrics(),
+        });
+    }
+}
@@ -0,0 +1,131 @@
+use agent_core::metrics::{Metrics, NetworkInterfaceMetrics, NetworkRateMetrics};
+use std::fs::read_to_string;
+use std::sync::Mutex;
+
+use crate::helpers::{matches_pattern, rate, with_previous, CounterSnapshot, Counters};
+use crate::Collector;
+
+const NET_DEV_PATH: &str = "/proc/net/dev";
+
+/// Raw counters for a single interface, as read from /proc/net/dev
+#[derive(Debug, Clone)]
+struct InterfaceCounters {
+    name: String,
+    bytes_recv: u64,
+    packets_recv: u64,
+    errors_in: u64,
+    drops_in: u64,
+    bytes_sent: u64,
+    packets_sent: u64,
+    errors_out: u64,
+    drops_out: u64,
+}
+
+impl Counters for InterfaceCounters {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Parse the contents of /proc/net/dev into per-interface counters
+fn parse_net_dev(raw: &str) -> Vec<InterfaceCounters> {
+    raw.lines()
+        // first two lines are headers
+        .skip(2)
+        .filter_map(|line| {
+            let (name, values) = line.split_once(':')?;
+            let values = values
+                .split_whitespace()
+                .map(|v| v.parse::<u64>().ok())
+                .collect::<Option<Vec<u64>>>()?;
+            if values.len() < 16 {
+                return None;
+            }
+            Some(InterfaceCounters {
+                name: name.trim().to_string(),
+                bytes_recv: values[0],
+                packets_recv: values[1],
+                errors_in: values[2],
+                drops_in: values[3],
+                bytes_sent: values[8],
+                packets_sent: values[9],
+                errors_out: values[10],
+                drops_out: values[11],
+            })
+        })
+        .collect()
+}
+
+/// Read the current counters of every interface
+fn read_counters() -> std::io::Result<Vec<InterfaceCounters>> {
+    Ok(parse_net_dev(&read_to_string(NET_DEV_PATH)?))
+}
+
+/// Convert an interface's counters into metrics, calculating rates from the previous counters
+fn to_metrics(
+    current: &InterfaceCounters,
+    previous: Option<(&InterfaceCounters, f64)>,
+) -> NetworkInterfaceMetrics {
+    let rates = previous.map(|(last, elapsed)| NetworkRateMetrics {
+        bytes_sent: rate(current.bytes_sent, last.bytes_sent, elapsed),
+        bytes_recv: rate(current.bytes_recv, last.bytes_recv, elapsed),
+        packets_sent: rate(current.packets_sent, last.packets_sent, elapsed),
+        packets_recv: rate(current.packets_recv, last.packets_recv, elapsed),
+    });
+    NetworkInterfaceMetrics {
+        name: current.name.clone(),
+        bytes_sent: current.bytes_sent,
+        bytes_recv: current.bytes_recv,
+        packets_sent: current.packets_sent,
+        packets_recv: current.packets_recv,
+        errors_in: current.errors_in,
+        errors_out: current.errors_out,
+        drops_in: current.drops_in,
+        drops_out: current.drops_out,
+        rates,
+    }
+}
+
+/// Collects network interface counters and throughput
+pub(crate) struct NetworkCollector {
+    exclude: Vec<String>,
+    snapshot: Mutex<Option<CounterSnapshot<InterfaceCounters>>>,
+}
+
+impl NetworkCollector {
+    pub fn new(exclude: Vec<String>) -> Self {
+        Self {
+            exclude,
+            snapshot: Mutex::new(None),
+        }
+    }
+}
+
+impl Collector for NetworkCollector {
+    fn name(&self) -> &str {
+        "network"
+    }
+    fn collect(&self, metrics: &mut Metrics) {
+        let counters = match read_counters() {
+            Ok(v) => v,
+            Err(err) => {
+                log::error!("unable to read network interfaces due to '{err}'");
+                return;
+            }
+        };
+        let counters = counters
+            .into_iter()
+            .filter(|counter| {
+                !self
+                    .exclude
+                    .iter()
+                    .any(|pattern| matches_pattern(pattern, &counter.name))
+            })
+            .collect();
+
+        let mut snapshot = self.snapshot.lock().unwrap();
+        let (network, new_snapshot) = with_previous(counters, snapshot.as_ref(), to_metrics);
+        *snapshot = Some(new_snapshot);
+        metrics.network = Some(network);
+    }
+}
@@ -0,0 +1,73 @@
+use agent_core::metrics::{
+    Metrics, PressureMetrics, PressureResourceMetrics, PressureStallMetrics,
+};
+use std::fs::read_to_string;
+use std::path::Path;
+
+use crate::Collector;
+
+const PRESSURE_ROOT: &str = "/proc/pressure";
+
+/// Parse a PSI line such as: 'some avg10=0.00 avg60=0.00 avg300=0.00 total=0'
+fn parse_stall(line: &str) -> Option<PressureStallMetrics> {
+    let mut stall = PressureStallMetrics {
+        avg10: 0.0,
+        avg60: 0.0,
+        avg300: 0.0,
+        total: 0,
+    };
+    for (key, value) in line
+        .split_whitespace()
+        .skip(1)
+        .filter_map(|field| field.split_once('='))
+    {
+        match key {
+            "avg10" => stall.avg10 = value.parse().ok()?,
+            "avg60" => stall.avg60 = value.parse().ok()?,
+            "avg300" => stall.avg300 = value.parse().ok()?,
+            "total" => stall.total = value.parse().ok()?,
+            _ => {}
+        }
+    }
+    Some(stall)
+}
+
+/// Read the pressure of a single resource (cpu, memory or io)
+fn read_resource(name: &str) -> Option<PressureResourceMetrics> {
+    let raw = read_to_string(Path::new(PRESSURE_ROOT).join(name)).ok()?;
+    let find = |prefix: &str| {
+        raw.lines()
+            .find(|line| line.starts_with(prefix))
+            .and_then(parse_stall)
+    };
+    Some(PressureResourceMetrics {
+        some: find("some ")?,
+        full: find("full "),
+    })
+}
+
+/// Read pressure stall information, returning None when not supported by the kernel.
+/// /proc/pressure exists but can not be read when PSI is disabled (e.g. booted with psi=0)
+fn read_pressure() -> Option<PressureMetrics> {
+    let pressure = PressureMetrics {
+        cpu: read_resource("cpu"),
+        memory: read_resource("memory"),
+        io: read_resource("io"),
+    };
+    if pressure.cpu.is_none() && pressure.memory.is_none() && pressure.io.is_none() {
+        return None;
+    }
+    Some(pressure)
+}
+
+/// Collects pressure stall information
+pub(crate) struct PressureCollector;
+
+impl Collector for PressureCollector {
+    fn name(&self) -> &str {
+        "pressure"
+    }
+    fn collect(&self, metrics: &mut Metrics) {
+        metrics.pressure = read_pressure();
+    }
+}
@@ -0,0 +1,212 @@
+use agent_config::types::ProcessesConfig;
+use agent_core::metrics::{Metrics, ProcessMetrics, ProcessesMetrics};
+use nix::unistd::{Uid, User};
+use psutil::process::os::unix::ProcessExt;
+use psutil::process::{Process, ProcessCollector, Status};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::Collector;
+
+/// Name used when reporting a process state
+fn status_name(status: &Status) -> &'static str {
+    match status {
+        Status::Running => "running",
+        Status::Sleeping => "sleeping",
+        Status::DiskSleep => "disk_sleep",
+        Status::Stopped => "stopped",
+        Status::TracingStop => "tracing_stop",
+        Status::Zombie => "zombie",
+        Status::Dead => "dead",
+        Status::WakeKill => "wake_kill",
+        Status::Waking => "waking",
+        Status::Parked => "parked",
+        Status::Idle => "idle",
+        Status::Locked => "locked",
+        Status::Waiting => "waiting",
+        Status::Suspended => "suspended",
+    }
+}
+
+/// Resolve a uid to a username, caching lookups for the current capture
+fn username(uid: u32, cache: &mut HashMap<u32, Option<String>>) -> Option<String> {
+    cache
+        .entry(uid)
+        .or_insert_with(|| {
+            User::from_uid(Uid::from_raw(uid))
+                .ok()
+                .flatten()
+                .map(|user| user.name)
+        })
+        .clone()
+}
+
+/// Resource usage of a process, captured once per refresh
+#[derive(Clone)]
+pub(crate) struct ProcessSample {
+    pub pid: u32,
+    pub status: Status,
+    pub cpu_perc: f32,
+    pub rss: u64,
+}
+
+/// Build the reported metrics of a single process
+fn to_metrics(
+    process: &Process,
+    sample: &ProcessSample,
+    include_cmdline: bool,
+    user_cache: &mut HashMap<u32, Option<String>>,
+) -> ProcessMetrics {
+    ProcessMetrics {
+        pid: sample.pid,
+        name: process.name().unwrap_or_default(),
+        cmdline: match include_cmdline {
+            true => process.cmdline().ok().flatten(),
+            false => None,
+        },
+        user: process
+            .uids()
+            .ok()
+            .and_then(|uids| username(uids.real, user_cache)),
+        cpu_perc: sample.cpu_perc,
+        rss: sample.rss,
+    }
+}
+
+/// Samples refreshed within this long are reused, so collectors in the same capture share them
+const REUSE_FOR: Duration = Duration::from_millis(500);
+
+/// Known processes, kept between captures so cpu percent can be calculated
+pub(crate) struct ProcessTable {
+    collector: Option<ProcessCollector>,
+    /// When the last refresh was & its samples
+    last: Option<(Instant, Vec<ProcessSample>)>,
+}
+
+/// Process table shared by the processes & watched collectors, so /proc is read once per capture
+pub(crate) type SharedProcessTable = Arc<Mutex<ProcessTable>>;
+
+impl ProcessTable {
+    pub fn shared() -> SharedProcessTable {
+        Arc::new(Mutex::new(Self {
+            collector: None,
+            last: None,
+        }))
+    }
+    /// Refresh the known processes, sampling the resource usage of each.
+    /// A refresh made moments ago is reused, as cpu percent needs time between refreshes
+    pub fn refresh(&mut self) -> Option<(&ProcessCollector, Vec<ProcessSample>)> {
+        if let Some((refreshed_at, samples)) = &self.last {
+            if refreshed_at.elapsed() < REUSE_FOR {
+                return Some((self.collector.as_ref()?, samples.clone()));
+            }
+        }
+        // collector is created on first use, as it reads every process
+        if self.collector.is_none() {
+            match ProcessCollector::new() {
+                Ok(v) => self.collector = Some(v),
+                Err(err) => {
+                    log::error!("unable to read processes due to '{err}'");
+                    return None;
+                }
+            }
+        }
+        let collector = self.collector.as_mut().unwrap();
+        if let Err(err) = collector.update() {
+            log::error!("unable to read processes due to '{err}'");
+            return None;
+        }
+
+        let mut samples: Vec<ProcessSample> = Vec::with_capacity(collector.processes.len());
+        for (pid, process) in collector.processes.iter_mut() {
+            // processes may exit while being read, these are skipped
+            let status = match process.status() {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+            samples.push(ProcessSample {
+                pid: *pid,
+                status,
+                cpu_perc: process.cpu_percent().unwrap_or_default(),
+                rss: process
+                    .memory_info()
+                    .map(|memory| memory.rss())
+                    .unwrap_or_default(),
+            });
+        }
+        self.last = Some((Instant::now(), samples.clone()));
+        Some((collector, samples))
+    }
+}
+
+/// Count processes by state and find the top resource users
+fn summarise(
+    collector: &ProcessCollector,
+    samples: &mut [ProcessSample],
+    top_n: usize,
+    include_cmdline: bool,
+) -> ProcessesMetrics {
+    let mut by_state: BTreeMap<String, u64> = BTreeMap::new();
+    for sample in samples.iter() {
+        *by_state
+            .entry(status_name(&sample.status).to_string())
+            .or_default() += 1;
+    }
+
+    let mut user_cache = HashMap::new();
+    let mut top_by = |compare: fn(&ProcessSample, &ProcessSample) -> std::cmp::Ordering| {
+        samples.sort_by(compare);
+        samples
+            .iter()
+            .take(top_n)
+            .filter_map(|sample| {
+                let process = collector.processes.get(&sample.pid)?;
+                Some(to_metrics(
+                    process,
+                    sample,
+                    include_cmdline,
+                    &mut user_cache,
+                ))
+            })
+            .collect::<Vec<ProcessMetrics>>()
+    };
+    let top_cpu = top_by(|a, b| b.cpu_perc.total_cmp(&a.cpu_perc));
+    let top_memory = top_by(|a, b| b.rss.cmp(&a.rss));
+
+    ProcessesMetrics {
+        total: samples.len() as u64,
+        by_state,
+        top_cpu,
+        top_memory,
+    }
+}
+
+/// Collects process counts and the top resource users
+pub(crate) struct ProcessesCollector {
+    config: ProcessesConfig,
+    table: SharedProcessTable,
+}
+
+impl ProcessesCollector {
+    pub fn new(config: ProcessesConfig, table: SharedProcessTable) -> Self {
+        Self { config, table }
+    }
+}
+
+impl Collector for ProcessesCollector {
+    fn name(&self) -> &str {
+        "processes"
+    }
+    fn collect(&self, metrics: &mut Metrics) {
+        let mut table = self.table.lock().unwrap();
+        if let Some((collector, mut samples)) = table.refresh() {
+            metrics.processes = Some(summarise(
+                collector,
+                &mut samples,
+                self.config.top_n,
+                self.config.include_cmdline,
+            ));
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use agent_core::metrics::{Metrics, SensorMetrics};
+use std::fs::{read_dir, read_to_string};
+use std::path::{Path, PathBuf};
+
+use crate::Collector;
+
+/// Read a sysfs attribute, trimming the trailing newline
+fn read_attr(path: &Path) -> Option<String> {
+    read_to_string(path).ok().map(|v| v.trim().to_string())
+}
+
+/// Read a sysfs temperature, which is given in millidegrees celsius
+fn read_temp(path: &Path) -> Option<f32> {
+    read_attr(path)?
+        .parse::<i64>()
+        .ok()
+        .map(|v| v as f32 / 1000.0)
+}
+
+/// List directory entries whose names start with a prefix, sorted by name
+fn entries_with_prefix(dir: &Path, prefix: &str) -> Vec<PathBuf> {
+    let mut entries = match read_dir(dir) {
+        Ok(v) => v
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with(prefix))
+            .map(|entry| entry.path())
+            .collect::<Vec<PathBuf>>(),
+        Err(_) => return vec![],
+    };
+    entries.sort();
+    entries
+}
+
+/// Read thermal zones from '{root}/class/thermal'
+fn thermal_zones(sysfs_root: &Path) -> Vec<SensorMetrics> {
+    entries_with_prefix(&sysfs_root.join("class/thermal"), "thermal_zone")
+        .iter()
+        .filter_map(|zone| {
+            let current = read_temp(&zone.join("temp"))?;
+            let source = zone.file_name().unwrap().to_string_lossy().to_string();
+            let label = read_attr(&zone.join("type")).unwrap_or_else(|| source.clone());
+            // thresholds are given as numbered trip points
+            let mut high = None;
+            let mut critical = None;
+            for trip_type in entries_with_prefix(zone, "trip_point_") {
+                let trip_name = trip_type.file_name().unwrap().to_string_lossy().to_string();
+                let trip_name = match trip_name.strip_suffix("_type") {
+                    Some(v) => v.to_string(),
+                    None => continue,
+                };
+                let temp = read_temp(&zone.join(format!("{trip_name}_temp")));
+                match read_attr(&trip_type).as_deref() {
+                    Some("critical") => critical = critical.or(temp),
+                    Some("hot") => high = temp.or(high),
+                    Some("passive") => high = high.or(temp),
+                    _ => {}
+                }
+            }
+            Some(SensorMetrics {
+                label,
+                source,
+                current,
+                high,
+                critical,
+            })
+        })
+        .collect()
+}
+
+/// Read temperature inputs from '{root}/class/hwmon'
+fn hwmon_temps(sysfs_root: &Path) -> Vec<SensorMetrics> {
+    entries_with_prefix(&sysfs_root.join("class/hwmon"), "hwmon")
+        .iter()
+        .flat_map(|hwmon| {
+            let chip = hwmon.file_name().unwrap().to_string_lossy().to_string();
+            let name = read_attr(&hwmon.join("name")).unwrap_or_else(|| chip.clone());
+            entries_with_prefix(hwmon, "temp")
+                .into_iter()
+                .filter_map(|input| {
+                    let input_name = input.file_name()?.to_string_lossy().to_string();
+                    let sensor = input_name.strip_suffix("_input")?.to_string();
+                    let current = read_temp(&input)?;
+                    let label = match read_attr(&hwmon.join(format!("{sensor}_label"))) {
+                        Some(v) => format!("{name} {v}"),
+                        None => format!("{name} {sensor}"),
+                    };
+                    Some(SensorMetrics {
+                        label,
+                        source: format!("{chip}/{sensor}"),
+                        current,
+                        high: read_temp(&hwmon.join(format!("{sensor}_max"))),
+                        critical: read_temp(&hwmon.join(format!("{sensor}_crit"))),
+                    })
+                })
+                .collect::<Vec<SensorMetrics>>()
+        })
+        .collect()
+}
+
+/// Collects temperature sensors from sysfs
+pub(crate) struct SensorsCollector {
+    sysfs_root: PathBuf,
+}
+
+impl SensorsCollector {
+    pub fn new(sysfs_root: PathBuf) -> Self {
+        Self { sysfs_root }
+    }
+}
+
+impl Collector for SensorsCollector {
+    fn name(&self) -> &str {
+        "sensors"
+    }
+    fn collect(&self, metrics: &mut Metrics) {
+        let mut sensors = thermal_zones(&self.sysfs_root);
+        sensors.extend(hwmon_temps(&self.sysfs_root));
+        metrics.sensors = Some(sensors);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::test_fixture as fixture;
+
+    #[test]
+    fn thermal_zones_read_temperatures_and_trip_points() {
+        let root = fixture(&[
+            ("class/thermal/thermal_zone0/type", "acpitz"),
+            ("class/thermal/thermal_zone0/temp", "45500"),
+            ("class/thermal/thermal_zone0/trip_point_0_type", "passive"),
+            ("class/thermal/thermal_zone0/trip_point_0_temp", "80000"),
+            ("class/thermal/thermal_zone0/trip_point_1_type", "critical"),
+            ("class/thermal/thermal_zone0/trip_point_1_temp", "105000"),
+            // zones without a type are named after their directory
+            ("class/thermal/thermal_zone1/temp", "30000"),
+            // cooling devices are not zones
+            ("class/thermal/cooling_device0/type", "Processor"),
+        ]);
+        let zones = thermal_zones(root.path());
+        assert_eq!(zones.len(), 2);
+        assert_eq!(zones[0].label, "acpitz");
+        assert_eq!(zones[0].current, 45.5);
+        assert_eq!(zones[0].high, Some(80.0));
+        assert_eq!(zones[0].critical, Some(105.0));
+        assert_eq!(zones[1].label, "thermal_zone1");
+        assert_eq!(zones[1].source, "thermal_zone1");
+        assert_eq!(zones[1].high, None);
+        assert_eq!(zones[1].critical, None);
+    }
+
+    #[test]
+    fn thermal_zones_with_the_same_type_have_their_own_source() {
+        let root = fixture(&[
+            ("class/thermal/thermal_zone0/type", "acpitz"),
+            ("class/thermal/thermal_zone0/temp", "40000"),
+            ("class/thermal/thermal_zone1/type", "acpitz"),
+            ("class/thermal/thermal_zone1/temp", "50000"),
+        ]);
+        let zones = thermal_zones(root.path());
+        assert_eq!(zones[0].label, zones[1].label);
+        assert_eq!(zones[0].source, "thermal_zone0");
+        assert_eq!(zones[1].source, "thermal_zone1");
+    }
+
+    #[test]
+    fn thermal_zones_prefer_hot_trip_point_as_high() {
+        let root = fixture(&[
+            ("class/thermal/thermal_zone0/temp", "50000"),
+            ("class/thermal/thermal_zone0/trip_point_0_type", "passive"),
+            ("class/thermal/thermal_zone0/trip_point_0_temp", "70000"),
+            ("class/thermal/thermal_zone0/trip_point_1_type", "hot"),
+            ("class/thermal/thermal_zone0/trip_point_1_temp", "90000"),
+        ]);
+        assert_eq!(thermal_zones(root.path())[0].high, Some(90.0));
+    }
+
+    #[test]
+    fn thermal_zones_skip_unreadable_temperatures() {
+        let root = fixture(&[
+            ("class/thermal/thermal_zone0/type", "broken"),
+            ("class/thermal/thermal_zone0/temp", "not a number"),
+        ]);
+        assert!(thermal_zones(root.path()).is_empty());
+    }
+
+    #[test]
+    fn hwmon_temps_read_inputs_with_labels_and_limits() {
+        let root = fixture(&[
+            ("class/hwmon/hwmon0/name", "coretemp"),
+            ("class/hwmon/hwmon0/temp1_input", "51000"),
+            ("class/hwmon/hwmon0/temp1_label", "Package id 0"),
+            ("class/hwmon/hwmon0/temp1_max", "80000"),
+            ("class/hwmon/hwmon0/temp1_crit", "100000"),
+            ("class/hwmon/hwmon0/temp2_input", "48000"),
+            ("class/hwmon/hwmon1/temp1_input", "35000"),
+        ]);
+        let temps = hwmon_temps(root.path());
+        assert_eq!(temps.len(), 3);
+        assert_eq!(temps[0].label, "coretemp Package id 0");
+        assert_eq!(temps[0].source, "hwmon0/temp1");
+        assert_eq!(temps[0].current, 51.0);
+        assert_eq!(temps[0].high, Some(80.0));
+        assert_eq!(temps[0].critical, Some(100.0));
+        // inputs without a label are named after the sensor
+        assert_eq!(temps[1].label, "coretemp temp2");
+        assert_eq!(temps[1].high, None);
+        // chips without a name are named after their directory
+        assert_eq!(temps[2].label, "hwmon1 temp1");
+    }
+
+    #[test]
+    fn missing_sysfs_has_no_sensors() {
+        let root = fixture(&[]);
+        assert!(thermal_zones(root.path()).is_empty());
+        assert!(hwmon_temps(root.path()).is_empty());
+    }
+}
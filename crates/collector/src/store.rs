@@ -0,0 +1,318 @@
+use agent_config::types::StoreConfig;
+use agent_core::metrics::{MetricsRollup, RollupValue};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs::{create_dir_all, read_dir, remove_file, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::helpers::numeric_values;
+
+/// How many files the retention period is split over,
+/// files are only removed once everything in them has expired
+const SEGMENTS_PER_RETENTION: u64 = 16;
+/// How often to remove expired files
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+pub enum StoreError {
+    /// No rollups are stored for the requested interval
+    UnknownResolution,
+    Io(io::Error),
+}
+
+impl From<io::Error> for StoreError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// Seconds since the unix epoch
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|v| v.as_secs())
+        .unwrap_or_default()
+}
+
+/// Records of a single resolution, split into files by time
+struct Series {
+    dir: PathBuf,
+    /// Seconds covered by each file
+    span: u64,
+    retention: u64,
+    /// Field holding the time of each record
+    time_key: &'static str,
+}
+
+impl Series {
+    fn new(dir: PathBuf, retention: u64, interval: u64, time_key: &'static str) -> Self {
+        Self {
+            dir,
+            span: (retention / SEGMENTS_PER_RETENTION).max(interval).max(1),
+            retention,
+            time_key,
+        }
+    }
+    /// Start time of every file, oldest first
+    fn segments(&self) -> io::Result<Vec<u64>> {
+        let entries = match read_dir(&self.dir) {
+            Ok(v) => v,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err),
+        };
+        let mut segments: Vec<u64> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let path = entry.path();
+                match path.extension().and_then(|ext| ext.to_str()) {
+                    Some("jsonl") => path.file_stem()?.to_str()?.parse().ok(),
+                    _ => None,
+                }
+            })
+            .collect();
+        segments.sort_unstable();
+        Ok(segments)
+    }
+    fn segment_path(&self, start: u64) -> PathBuf {
+        self.dir.join(format!("{start}.jsonl"))
+    }
+    /// Append a record to the file covering the given time
+    fn append(&self, time: u64, record: &str) -> io::Result<()> {
+        create_dir_all(&self.dir)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.segment_path(time - time % self.span))?;
+        writeln!(file, "{record}")
+    }
+    /// Remove files where every record is older than the retention
+    fn prune(&self, now: u64) -> io::Result<()> {
+        for start in self.segments()? {
+            if start + self.span + self.retention < now {
+                log::debug!("removing expired metrics in '{}'", self.dir.display());
+                remove_file(self.segment_path(start))?;
+            }
+        }
+        Ok(())
+    }
+    /// Read records between the given times, oldest first
+    fn read(&self, from: u64, to: u64) -> io::Result<Vec<Value>> {
+        let mut records = vec![];
+        for start in self.segments()? {
+            if start > to || start + self.span <= from {
+                continue;
+            }
+            let file = BufReader::new(File::open(self.segment_path(start))?);
+            for line in file.lines() {
+                // a partially written record is skipped
+                let record: Value = match serde_json::from_str(&line?) {
+                    Ok(v) => v,
+                    Err(_) => continue,
+                };
+                let time = record[self.time_key]["secs_since_epoch"].as_u64();
+                if matches!(time, Some(time) if time >= from && time <= to) {
+                    records.push(record);
+                }
+            }
+        }
+        Ok(records)
+    }
+}
+
+/// Rollup being built for the current interval
+struct Bucket {
+    start: u64,
+    samples: u64,
+    /// Minimum, sum, maximum & count of each value, as not every capture has every value
+    values: BTreeMap<String, (f64, f64, f64, u64)>,
+}
+
+impl Bucket {
+    fn new(start: u64) -> Self {
+        Self {
+            start,
+            samples: 0,
+            values: BTreeMap::new(),
+        }
+    }
+    fn add(&mut self, values: &BTreeMap<String, f64>) {
+        self.samples += 1;
+        for (key, value) in values {
+            let (min, sum, max, count) = self
+                .values
+                .entry(key.to_string())
+                .or_insert((*value, 0.0, *value, 0));
+            *min = min.min(*value);
+            *sum += value;
+            *max = max.max(*value);
+            *count += 1;
+        }
+    }
+    fn to_rollup(&self, interval: u64) -> MetricsRollup {
+        MetricsRollup {
+            start: UNIX_EPOCH + Duration::from_secs(self.start),
+            interval,
+            samples: self.samples,
+            values: self
+                .values
+                .iter()
+                .map(|(key, (min, sum, max, count))| {
+                    let value = RollupValue {
+                        min: *min,
+                        avg: sum / *count as f64,
+                        max: *max,
+                    };
+                    (key.to_string(), value)
+                })
+                .collect(),
+        }
+    }
+}
+
+struct Rollup {
+    interval: u64,
+    series: Series,
+    bucket: Mutex<Option<Bucket>>,
+}
+
+impl Rollup {
+    /// Write out a bucket, a later rollup with the same start replaces it when read
+    fn write(&self, bucket: &Bucket) -> io::Result<()> {
+        let record = serde_json::to_string(&bucket.to_rollup(self.interval))?;
+        self.series.append(bucket.start, &record)
+    }
+}
+
+/// Numeric values of a stored capture, by their path in the metrics
+fn record_values(record: &Value) -> BTreeMap<String, f64> {
+    let mut values = BTreeMap::new();
+    numeric_values("", &record["metrics"], &mut values);
+    values
+}
+
+/// On disk store of every capture, along with rollups of numeric values
+pub(crate) struct Store {
+    raw: Series,
+    rollups: Vec<Rollup>,
+    last_pruned: Mutex<Option<Instant>>,
+}
+
+impl Store {
+    pub fn new(path: &Path, config: &StoreConfig) -> Self {
+        let store = Self {
+            raw: Series::new(path.join("raw"), config.raw_retention, 1, "captured_at"),
+            rollups: config
+                .rollups
+                .iter()
+                .filter(|rollup| rollup.interval > 0)
+                .map(|rollup| Rollup {
+                    interval: rollup.interval,
+                    series: Series::new(
+                        path.join(rollup.interval.to_string()),
+                        rollup.retention,
+                        rollup.interval,
+                        "start",
+                    ),
+                    bucket: Mutex::new(None),
+                })
+                .collect(),
+            last_pruned: Mutex::new(None),
+        };
+        if let Err(err) = store.resume() {
+            log::error!("unable to resume metrics rollups due to '{err}'");
+        }
+        store
+    }
+    /// Rebuild the rollups of the current interval from stored captures,
+    /// so captures from before a restart are not missing from them
+    fn resume(&self) -> io::Result<()> {
+        let now = unix_secs(SystemTime::now());
+        for rollup in &self.rollups {
+            let start = now - now % rollup.interval;
+            let mut bucket = Bucket::new(start);
+            for record in self.raw.read(start, now)? {
+                bucket.add(&record_values(&record));
+            }
+            if bucket.samples > 0 {
+                log::debug!(
+                    "resumed '{}' second rollup from {} captures",
+                    rollup.interval,
+                    bucket.samples
+                );
+                *rollup.bucket.lock().unwrap() = Some(bucket);
+            }
+        }
+        Ok(())
+    }
+    /// Write out the unfinished rollups, so they are kept when the agent stops
+    pub fn flush(&self) -> io::Result<()> {
+        for rollup in &self.rollups {
+            if let Some(bucket) = rollup.bucket.lock().unwrap().take() {
+                rollup.write(&bucket)?;
+            }
+        }
+        Ok(())
+    }
+    /// Record a serialised capture taken at the given unix time, adding its numeric values to each rollup
+    pub fn record(
+        &self,
+        time: u64,
+        record: &str,
+        values: &BTreeMap<String, f64>,
+    ) -> io::Result<()> {
+        self.raw.append(time, record)?;
+
+        for rollup in &self.rollups {
+            let start = time - time % rollup.interval;
+            let mut bucket = rollup.bucket.lock().unwrap();
+            // write out the finished rollup once a capture falls in the next interval
+            let finished = match &*bucket {
+                Some(v) if v.start < start => bucket.take(),
+                _ => None,
+            };
+            if let Some(finished) = finished {
+                rollup.write(&finished)?;
+            }
+            bucket.get_or_insert_with(|| Bucket::new(start)).add(values);
+        }
+
+        let mut last_pruned = self.last_pruned.lock().unwrap();
+        if last_pruned.is_none_or(|v| v.elapsed() >= PRUNE_INTERVAL) {
+            *last_pruned = Some(Instant::now());
+            let now = unix_secs(SystemTime::now());
+            self.raw.prune(now)?;
+            for rollup in &self.rollups {
+                rollup.series.prune(now)?;
+            }
+        }
+        Ok(())
+    }
+    /// Read stored captures, or rollups of the given interval, between the given unix times
+    pub fn query(
+        &self,
+        interval: Option<u64>,
+        from: u64,
+        to: u64,
+    ) -> Result<Vec<Value>, StoreError> {
+        let rollup = match interval {
+            None => return Ok(self.raw.read(from, to)?),
+            Some(interval) => self
+                .rollups
+                .iter()
+                .find(|rollup| rollup.interval == interval)
+                .ok_or(StoreError::UnknownResolution)?,
+        };
+        // rollups flushed on shutdown are written again once their interval finishes,
+        // only the last written for each start is kept
+        let mut rollups: Vec<Value> = vec![];
+        for record in rollup.series.read(from, to)? {
+            match rollups.last_mut() {
+                Some(last) if last["start"] == record["start"] => *last = record,
+                _ => rollups.push(record),
+            }
+        }
+        Ok(rollups)
+    }
+}
@@ -0,0 +1,45 @@
+use agent_core::metrics::{CpuCountMetrics, LoadAverageMetrics, Metrics, SystemMetrics};
+use nix::sys::utsname::uname;
+use std::fs::read_to_string;
+
+use crate::Collector;
+
+const OS_RELEASE_PATH: &str = "/etc/os-release";
+
+/// Collects host details, load averages and uptime
+pub(crate) struct SystemCollector;
+
+impl Collector for SystemCollector {
+    fn name(&self) -> &str {
+        "system"
+    }
+    fn collect(&self, metrics: &mut Metrics) {
+        let uts = uname();
+        // prefer the distribution name over the kernel name
+        let os_name = read_to_string(OS_RELEASE_PATH)
+            .ok()
+            .and_then(|raw| {
+                raw.lines()
+                    .find_map(|line| line.strip_prefix("PRETTY_NAME="))
+                    .map(|v| v.trim_matches('"').to_string())
+            })
+            .unwrap_or_else(|| uts.sysname().to_string());
+
+        metrics.system = Some(SystemMetrics {
+            hostname: uts.nodename().to_string(),
+            os_name,
+            kernel_release: uts.release().to_string(),
+            uptime: psutil::host::uptime().ok().map(|v| v.as_secs()),
+            boot_time: psutil::host::boot_time().ok(),
+            load_average: psutil::host::loadavg().ok().map(|load| LoadAverageMetrics {
+                one: load.one as f32,
+                five: load.five as f32,
+                fifteen: load.fifteen as f32,
+            }),
+            cpu_count: CpuCountMetrics {
+                logical: psutil::cpu::cpu_count(),
+                physical: psutil::cpu::cpu_count_physical(),
+            },
+        });
+    }
+}
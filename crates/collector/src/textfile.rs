@@ -0,0 +1,108 @@
+use agent_config::types::TextfileConfig;
+use agent_core::metrics::{CustomValue, Metrics, TextfileMetrics};
+use std::collections::BTreeMap;
+use std::fs::{read_dir, read_to_string};
+use std::path::Path;
+
+use crate::helpers::parse_json;
+use crate::Collector;
+
+/// Parse a Prometheus text exposition file,
+/// samples are keyed by their name and labels (e.g. 'backup_success{job="db"}')
+fn parse_prom(raw: &str) -> Result<BTreeMap<String, CustomValue>, String> {
+    let mut values = BTreeMap::new();
+    for line in raw
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+    {
+        // label values may contain whitespace, so split after the closing brace
+        let split_at = match line.find('{') {
+            Some(_) => line.rfind('}').map(|i| i + 1),
+            None => line.find(char::is_whitespace),
+        };
+        let (key, rest) = match split_at {
+            Some(i) => line.split_at(i),
+            None => return Err(format!("invalid line: '{line}'")),
+        };
+        // an optional timestamp may follow the value
+        let value = rest
+            .split_whitespace()
+            .next()
+            .and_then(|v| v.parse::<f64>().ok())
+            .ok_or_else(|| format!("invalid value in line: '{line}'"))?;
+        values.insert(key.to_string(), CustomValue::Number(value));
+    }
+    Ok(values)
+}
+
+/// Read a single metrics file, parse errors are reported in the metrics
+fn read_file(path: &Path, file: String) -> TextfileMetrics {
+    let modified = path.metadata().and_then(|meta| meta.modified()).ok();
+    let parsed = read_to_string(path)
+        .map_err(|err| format!("unable to read: {err}"))
+        .and_then(|raw| match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => parse_json(&raw),
+            _ => parse_prom(&raw),
+        });
+    let (values, error) = match parsed {
+        Ok(values) => (values, None),
+        Err(err) => {
+            log::warn!("textfile '{file}' could not be parsed: {err}");
+            (BTreeMap::new(), Some(err))
+        }
+    };
+    TextfileMetrics {
+        file,
+        modified,
+        values,
+        error,
+    }
+}
+
+/// Collects metrics from files written by other programs
+pub(crate) struct TextfileCollector {
+    config: TextfileConfig,
+}
+
+impl TextfileCollector {
+    pub fn new(config: TextfileConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl Collector for TextfileCollector {
+    fn name(&self) -> &str {
+        "textfile"
+    }
+    fn collect(&self, metrics: &mut Metrics) {
+        let entries = match read_dir(&self.config.directory) {
+            Ok(v) => v,
+            Err(err) => {
+                log::debug!(
+                    "unable to read textfile directory '{}': {err}",
+                    self.config.directory.display()
+                );
+                metrics.textfile = Some(vec![]);
+                return;
+            }
+        };
+        let mut files: Vec<TextfileMetrics> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.is_file()
+                    && matches!(
+                        path.extension().and_then(|ext| ext.to_str()),
+                        Some("json" | "prom")
+                    )
+            })
+            .filter_map(|path| {
+                let file = path.file_name()?.to_string_lossy().to_string();
+                Some(read_file(&path, file))
+            })
+            .collect();
+        files.sort_by(|a, b| a.file.cmp(&b.file));
+        metrics.textfile = Some(files);
+    }
+}
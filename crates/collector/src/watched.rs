@@ -0,0 +1,182 @@
+use agent_config::types::WatchedProcessConfig;
+use agent_core::metrics::{Metrics, WatchedProcessMetrics};
+use psutil::process::{Process, ProcessCollector};
+use regex::Regex;
+use std::fs::read_to_string;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::processes::{ProcessSample, SharedProcessTable};
+use crate::Collector;
+
+/// Compiled form of a watched process config
+struct Matcher {
+    name: String,
+    exe: Option<String>,
+    cmdline: Option<Regex>,
+    pidfile: Option<PathBuf>,
+}
+
+impl Matcher {
+    /// Create a matcher, returning why if the config is invalid
+    fn new(config: &WatchedProcessConfig) -> Result<Self, String> {
+        if config.exe.is_none() && config.cmdline.is_none() && config.pidfile.is_none() {
+            return Err("needs one of exe, cmdline or pidfile".to_string());
+        }
+        let cmdline = match &config.cmdline {
+            Some(pattern) => {
+                Some(Regex::new(pattern).map_err(|err| format!("invalid cmdline regex: {err}"))?)
+            }
+            None => None,
+        };
+        Ok(Self {
+            name: config.name.clone(),
+            exe: config.exe.clone(),
+            cmdline,
+            pidfile: config.pidfile.clone(),
+        })
+    }
+    /// Pid given in the pidfile, if one is configured and readable
+    fn pidfile_pid(&self) -> Option<u32> {
+        let raw = read_to_string(self.pidfile.as_ref()?).ok()?;
+        raw.trim().parse::<u32>().ok()
+    }
+    /// Check whether a process matches every configured criteria
+    fn is_match(&self, pid: u32, process: &Process, pidfile_pid: Option<u32>) -> bool {
+        if self.pidfile.is_some() && pidfile_pid != Some(pid) {
+            return false;
+        }
+        if let Some(exe) = &self.exe {
+            let exe_name = process
+                .exe()
+                .ok()
+                .and_then(|path| Some(path.file_name()?.to_string_lossy().to_string()));
+            // name is used as a fallback, as exe is not readable for other users processes
+            let matched = match exe_name {
+                Some(v) => v == *exe,
+                None => process.name().map(|v| v == *exe).unwrap_or(false),
+            };
+            if !matched {
+                return false;
+            }
+        }
+        if let Some(cmdline) = &self.cmdline {
+            match process.cmdline() {
+                Ok(Some(v)) if cmdline.is_match(&v) => {}
+                _ => return false,
+            }
+        }
+        true
+    }
+    /// Find all processes matching and report their combined usage
+    fn check(
+        &self,
+        collector: &ProcessCollector,
+        samples: &[ProcessSample],
+        system_uptime: Option<Duration>,
+    ) -> WatchedProcessMetrics {
+        let pidfile_pid = self.pidfile_pid();
+        let matched = samples
+            .iter()
+            .filter(|sample| match collector.processes.get(&sample.pid) {
+                Some(process) => self.is_match(sample.pid, process, pidfile_pid),
+                None => false,
+            })
+            .collect::<Vec<&ProcessSample>>();
+        // uptime is taken from the longest running process
+        let uptime = system_uptime.and_then(|system_uptime| {
+            matched
+                .iter()
+                .filter_map(|sample| collector.processes.get(&sample.pid))
+                .map(|process| process.create_time())
+                .min()
+                .map(|started| system_uptime.saturating_sub(started).as_secs())
+        });
+        WatchedProcessMetrics {
+            name: self.name.clone(),
+            running: !matched.is_empty(),
+            pids: matched.iter().map(|sample| sample.pid).collect(),
+            uptime,
+            cpu_perc: matched
+                .iter()
+                .fold(0.0, |total, sample| total + sample.cpu_perc),
+            rss: matched.iter().map(|sample| sample.rss).sum(),
+            error: None,
+        }
+    }
+}
+
+/// A watched process config, or why it is invalid
+enum Watch {
+    Valid(Matcher),
+    Invalid { name: String, error: String },
+}
+
+impl Watch {
+    fn new(config: &WatchedProcessConfig) -> Self {
+        match Matcher::new(config) {
+            Ok(matcher) => Self::Valid(matcher),
+            Err(error) => {
+                log::error!("watched process '{}' is invalid: {error}", config.name);
+                Self::Invalid {
+                    name: config.name.clone(),
+                    error,
+                }
+            }
+        }
+    }
+}
+
+/// Collects the liveness of watched processes
+pub(crate) struct WatchedCollector {
+    /// Invalid watches are kept, so they are reported instead of disappearing
+    watches: Vec<Watch>,
+    table: SharedProcessTable,
+}
+
+impl WatchedCollector {
+    pub fn new(configs: &[WatchedProcessConfig], table: SharedProcessTable) -> Self {
+        Self {
+            watches: configs.iter().map(Watch::new).collect(),
+            table,
+        }
+    }
+}
+
+impl Collector for WatchedCollector {
+    fn name(&self) -> &str {
+        "watched"
+    }
+    fn collect(&self, metrics: &mut Metrics) {
+        // avoid reading every process when nothing can be matched
+        let mut table = self.table.lock().unwrap();
+        let refreshed = match self.watches.iter().any(|v| matches!(v, Watch::Valid(_))) {
+            true => match table.refresh() {
+                Some(v) => Some(v),
+                None => return,
+            },
+            false => None,
+        };
+        let system_uptime = psutil::host::uptime().ok();
+        metrics.watched = Some(
+            self.watches
+                .iter()
+                .map(|watch| match (watch, &refreshed) {
+                    (Watch::Valid(matcher), Some((collector, samples))) => {
+                        matcher.check(collector, samples, system_uptime)
+                    }
+                    (Watch::Invalid { name, error }, _) => WatchedProcessMetrics {
+                        name: name.clone(),
+                        running: false,
+                        pids: vec![],
+                        uptime: None,
+                        cpu_perc: 0.0,
+                        rss: 0,
+                        error: Some(error.clone()),
+                    },
+                    (Watch::Valid(_), None) => unreachable!("processes are read for valid watches"),
+                })
+                .collect(),
+        );
+    }
+}
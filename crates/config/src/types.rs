@@ -1,4 +1,6 @@
+use agent_core::alerts::{AlertComparator, AlertSeverity};
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::net::IpAddr;
 use std::path::PathBuf;
 use uuid::Uuid;
@@ -9,7 +11,7 @@ pub struct CertificateConfig {
     pub public_path: PathBuf,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Default)]
 #[serde(default)]
 pub struct AuthenticationConfig {
     pub check_ip: bool,
@@ -18,17 +20,6 @@ pub struct AuthenticationConfig {
     pub allowed_keys: Vec<String>,
 }
 
-impl Default for AuthenticationConfig {
-    fn default() -> Self {
-        AuthenticationConfig {
-            check_ip: false,
-            check_key: false,
-            allowed_ip: vec![],
-            allowed_keys: vec![],
-        }
-    }
-}
-
 #[derive(Debug, Clone, Deserialize)]
 pub struct WebConfig {
     pub host: String,
@@ -50,12 +41,70 @@ impl Default for WebConfig {
     }
 }
 
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WebhooksRetryConfig {
+    /// Attempts to make before giving up, including the first
+    pub max_attempts: u32,
+    /// Milliseconds to wait before the first retry
+    pub initial_delay: u64,
+    /// How much the delay grows by after each retry
+    pub multiplier: f64,
+    /// Fraction of the delay to randomly add or remove (e.g. 0.1 for +/-10%)
+    pub jitter: f64,
+    /// Response status codes that are retried, connection errors are always retried
+    pub retry_statuses: Vec<u16>,
+}
+
+impl Default for WebhooksRetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_delay: 1000,
+            multiplier: 2.0,
+            jitter: 0.1,
+            retry_statuses: vec![408, 429, 500, 502, 503, 504],
+        }
+    }
+}
+
+/// Request body sent to a hook
+#[derive(Debug, Clone, Copy, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhooksFormat {
+    /// The agent's own JSON body
+    #[default]
+    Json,
+    /// Slack incoming webhook message
+    Slack,
+    /// Discord webhook message
+    Discord,
+    /// Microsoft Teams adaptive card
+    Teams,
+    /// ntfy notification, the url should include the topic
+    Ntfy,
+    /// User supplied template file, rendered against the JSON body
+    Template,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct WebhooksHookConfig {
     /// Where to send the request
     pub url: String,
     /// Used when signing the body with HMAC
     pub secret: Option<String>,
+    /// PEM encoded Ed25519 private key, used when signing so receivers only need the public key
+    pub signing_key: Option<PathBuf>,
+    #[serde(default)]
+    pub retry: WebhooksRetryConfig,
+    /// Shape of the request body
+    #[serde(default)]
+    pub format: WebhooksFormat,
+    /// Template file to render the body with, required when the format is template
+    pub template: Option<PathBuf>,
+    /// Extra headers sent with every request (e.g. API tokens)
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -64,6 +113,18 @@ pub struct WebhooksHookConfigIntervalMetrics {
     pub url: String,
     /// Used when signing the body with HMAC
     pub secret: Option<String>,
+    /// PEM encoded Ed25519 private key, used when signing so receivers only need the public key
+    pub signing_key: Option<PathBuf>,
+    #[serde(default)]
+    pub retry: WebhooksRetryConfig,
+    /// Shape of the request body
+    #[serde(default)]
+    pub format: WebhooksFormat,
+    /// Template file to render the body with, required when the format is template
+    pub template: Option<PathBuf>,
+    /// Extra headers sent with every request (e.g. API tokens)
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
     pub interval: u64,
 }
 
@@ -72,17 +133,351 @@ impl WebhooksHookConfigIntervalMetrics {
         WebhooksHookConfig {
             url: self.url.clone(),
             secret: self.secret.clone(),
+            signing_key: self.signing_key.clone(),
+            retry: self.retry.clone(),
+            format: self.format,
+            template: self.template.clone(),
+            headers: self.headers.clone(),
         }
     }
 }
 
-#[derive(Debug, Clone, Deserialize, Default)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WebhooksOutboxConfig {
+    /// Directory to queue undelivered webhooks in, the outbox is disabled when not given
+    pub path: Option<PathBuf>,
+    /// Most webhooks to queue, the oldest are dropped once exceeded
+    pub max_entries: usize,
+    /// Seconds to keep queued webhooks for
+    pub max_age: u64,
+    /// Seconds between attempts to deliver queued webhooks
+    pub retry_interval: u64,
+}
+
+impl Default for WebhooksOutboxConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            max_entries: 10_000,
+            max_age: 24 * 60 * 60,
+            retry_interval: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
 #[serde(default)]
 pub struct WebhooksConfig {
     /// Webhook triggered when agent is starting
     pub on_start: Vec<WebhooksHookConfig>,
+    /// Webhook triggered when agent is stopping
+    pub on_shutdown: Vec<WebhooksHookConfig>,
+    /// Seconds to wait for on_shutdown webhooks to be sent before exiting
+    pub shutdown_timeout: u64,
     pub interval_pings: Vec<WebhooksHookConfigIntervalMetrics>,
     pub interval_metrics: Vec<WebhooksHookConfigIntervalMetrics>,
+    /// Webhook triggered when a watched process starts or stops, checked every interval
+    pub on_watched_change: Vec<WebhooksHookConfigIntervalMetrics>,
+    /// Webhook triggered when an alert fires or resolves
+    pub on_alert: Vec<WebhooksHookConfig>,
+    /// Queue for webhooks that could not be delivered
+    pub outbox: WebhooksOutboxConfig,
+}
+
+impl WebhooksConfig {
+    /// Every configured hook
+    pub fn hooks(&self) -> Vec<WebhooksHookConfig> {
+        self.on_start
+            .iter()
+            .chain(self.on_shutdown.iter())
+            .chain(self.on_alert.iter())
+            .cloned()
+            .chain(
+                self.interval_pings
+                    .iter()
+                    .chain(self.interval_metrics.iter())
+                    .chain(self.on_watched_change.iter())
+                    .map(|hook| hook.into_base()),
+            )
+            .collect()
+    }
+    /// Paths of every Ed25519 private key used by a hook, without duplicates
+    pub fn signing_keys(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self
+            .hooks()
+            .into_iter()
+            .filter_map(|hook| hook.signing_key)
+            .collect();
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+}
+
+impl Default for WebhooksConfig {
+    fn default() -> Self {
+        Self {
+            on_start: vec![],
+            on_shutdown: vec![],
+            shutdown_timeout: 5,
+            interval_pings: vec![],
+            interval_metrics: vec![],
+            on_watched_change: vec![],
+            on_alert: vec![],
+            outbox: Default::default(),
+        }
+    }
+}
+
+/// Names of every collector, including those registered by servers (e.g. the webhook outbox)
+pub const COLLECTOR_NAMES: [&str; 14] = [
+    "cpu",
+    "memory",
+    "disks",
+    "disk_io",
+    "network",
+    "system",
+    "sensors",
+    "processes",
+    "watched",
+    "cgroups",
+    "pressure",
+    "custom",
+    "textfile",
+    "outbox",
+];
+
+/// Whether each collector is enabled, collectors not listed are enabled
+#[derive(Debug, Clone, Deserialize, Default)]
+#[serde(transparent)]
+pub struct CollectorsConfig {
+    pub enabled: HashMap<String, bool>,
+}
+
+impl CollectorsConfig {
+    pub fn is_enabled(&self, name: &str) -> bool {
+        *self.enabled.get(name).unwrap_or(&true)
+    }
+    /// Names listed in config that are not a collector, likely misspelt
+    pub fn unknown(&self) -> Vec<&str> {
+        let mut unknown: Vec<&str> = self
+            .enabled
+            .keys()
+            .map(String::as_str)
+            .filter(|name| !COLLECTOR_NAMES.contains(name))
+            .collect();
+        unknown.sort();
+        unknown
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DiskIoConfig {
+    /// Whether to exclude partitions, only reporting whole devices
+    pub exclude_partitions: bool,
+    /// Whether to exclude loop devices
+    pub exclude_loop: bool,
+    /// Device names to exclude, '*' matches any characters (e.g. "dm-*")
+    pub exclude: Vec<String>,
+}
+
+impl Default for DiskIoConfig {
+    fn default() -> Self {
+        DiskIoConfig {
+            exclude_partitions: true,
+            exclude_loop: true,
+            exclude: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Default)]
+#[serde(default)]
+pub struct NetworkConfig {
+    /// Interface names to exclude, '*' matches any characters (e.g. "veth*")
+    pub exclude: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SensorsConfig {
+    /// Where sysfs is mounted, can be changed to read from a fixture directory
+    pub sysfs_root: PathBuf,
+}
+
+impl Default for SensorsConfig {
+    fn default() -> Self {
+        SensorsConfig {
+            sysfs_root: PathBuf::from("/sys"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ProcessesConfig {
+    /// How many processes to list for the top cpu & memory users
+    pub top_n: usize,
+    /// Whether to include command lines, these may contain secrets
+    pub include_cmdline: bool,
+}
+
+impl Default for ProcessesConfig {
+    fn default() -> Self {
+        ProcessesConfig {
+            top_n: 5,
+            include_cmdline: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CgroupsConfig {
+    /// Where the cgroup v2 hierarchy is mounted, can be changed to read from a fixture directory
+    pub root: PathBuf,
+    /// Whether to report the cgroup the agent is running in
+    pub include_self: bool,
+    /// Additional cgroups to report, relative to the root (e.g. "system.slice/nginx.service")
+    pub paths: Vec<String>,
+}
+
+impl Default for CgroupsConfig {
+    fn default() -> Self {
+        CgroupsConfig {
+            root: PathBuf::from("/sys/fs/cgroup"),
+            include_self: true,
+            paths: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WatchedProcessConfig {
+    /// Name to report the process as
+    pub name: String,
+    /// Executable name to match (e.g. "nginx")
+    pub exe: Option<String>,
+    /// Regex to match against the full command line
+    pub cmdline: Option<String>,
+    /// File containing the pid of the process
+    pub pidfile: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TextfileConfig {
+    /// Directory to read '*.json' & '*.prom' files from
+    pub directory: PathBuf,
+}
+
+impl Default for TextfileConfig {
+    fn default() -> Self {
+        Self {
+            directory: PathBuf::from("/var/lib/monitoring-agent/textfile"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    /// Number of captures to keep, 0 disables history
+    pub length: usize,
+    /// Approximate memory to use, the oldest captures are dropped once exceeded
+    pub max_bytes: usize,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            length: 120,
+            max_bytes: 8 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RollupConfig {
+    /// Seconds summarised by each rollup
+    pub interval: u64,
+    /// Seconds to keep rollups for
+    pub retention: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct StoreConfig {
+    /// Directory to store metrics in, the store is disabled when not given
+    pub path: Option<PathBuf>,
+    /// Seconds to keep every capture for
+    pub raw_retention: u64,
+    pub rollups: Vec<RollupConfig>,
+}
+
+impl Default for StoreConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            raw_retention: 24 * 60 * 60,
+            rollups: vec![
+                RollupConfig {
+                    interval: 60,
+                    retention: 7 * 24 * 60 * 60,
+                },
+                RollupConfig {
+                    interval: 60 * 60,
+                    retention: 90 * 24 * 60 * 60,
+                },
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AlertRuleConfig {
+    pub name: String,
+    /// Path of the metric to check, '*' matches any characters (e.g. "disks.*.perc_used")
+    pub metric: String,
+    pub comparator: AlertComparator,
+    /// Threshold that fires the alert
+    pub threshold: f64,
+    /// Threshold the metric must pass back over to resolve, defaults to the threshold.
+    /// Set apart from the threshold so metrics hovering around it don't flap (e.g. fire at 90, clear at 80)
+    pub clear_threshold: Option<f64>,
+    /// Seconds the threshold must be breached for before firing, until then the alert is pending
+    #[serde(default, rename = "for")]
+    pub duration: u64,
+    /// Seconds between repeat notifications while firing, only notified once when not given
+    pub renotify: Option<u64>,
+    #[serde(default)]
+    pub severity: AlertSeverity,
+}
+
+fn default_command_timeout() -> u64 {
+    5
+}
+
+fn default_command_interval() -> u64 {
+    60
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CommandConfig {
+    /// Name to report the results under
+    pub name: String,
+    /// Executable to run
+    pub path: PathBuf,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Seconds to wait before killing the command
+    #[serde(default = "default_command_timeout")]
+    pub timeout: u64,
+    /// Seconds between runs, previous results are reported in between
+    #[serde(default = "default_command_interval")]
+    pub interval: u64,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -91,20 +486,64 @@ pub struct Config {
     /// Agent id; used in webhooks, should be unique if using multiple agents
     pub id: String,
     pub cache_for: u64,
+    /// Seconds between background captures,
+    /// when not given metrics are captured on request and cached for `cache_for`
+    pub sample_interval: Option<u64>,
     pub timeout: u64,
+    pub collectors: CollectorsConfig,
+    pub history: HistoryConfig,
+    pub store: StoreConfig,
+    pub disk_io: DiskIoConfig,
+    pub network: NetworkConfig,
+    pub sensors: SensorsConfig,
+    pub processes: ProcessesConfig,
+    /// Processes to report the liveness of
+    pub watched: Vec<WatchedProcessConfig>,
+    pub cgroups: CgroupsConfig,
+    /// Commands whose output is reported as custom metrics
+    pub commands: Vec<CommandConfig>,
+    /// Rules checked against every capture
+    pub alerts: Vec<AlertRuleConfig>,
+    pub textfile: TextfileConfig,
     #[cfg(feature = "web")]
     pub web: WebConfig,
     #[cfg(feature = "webhooks")]
     pub webhooks: WebhooksConfig,
 }
 
+impl Config {
+    /// Seconds between background captures, if any.
+    /// Alerts are only checked when metrics are captured, so if there are alert rules
+    /// metrics are sampled every `cache_for` seconds when `sample_interval` is not given
+    pub fn sampling_interval(&self) -> Option<u64> {
+        match self.sample_interval {
+            Some(v) => Some(v),
+            None if !self.alerts.is_empty() => Some(self.cache_for.max(1)),
+            None => None,
+        }
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         let agent_uuid = Uuid::new_v4();
         Config {
             id: agent_uuid.to_string(),
             cache_for: 1,
+            sample_interval: None,
             timeout: 4,
+            collectors: Default::default(),
+            history: Default::default(),
+            store: Default::default(),
+            disk_io: Default::default(),
+            network: Default::default(),
+            sensors: Default::default(),
+            processes: Default::default(),
+            watched: vec![],
+            cgroups: Default::default(),
+            commands: vec![],
+            alerts: vec![],
+            textfile: Default::default(),
             #[cfg(feature = "web")]
             web: Default::default(),
             #[cfg(feature = "webhooks")]
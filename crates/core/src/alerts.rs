@@ -0,0 +1,80 @@
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+/// How a metric is compared to the threshold of a rule
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum AlertComparator {
+    #[serde(rename = ">")]
+    GreaterThan,
+    #[serde(rename = ">=")]
+    GreaterThanOrEqual,
+    #[serde(rename = "<")]
+    LessThan,
+    #[serde(rename = "<=")]
+    LessThanOrEqual,
+    #[serde(rename = "==")]
+    Equal,
+    #[serde(rename = "!=")]
+    NotEqual,
+}
+
+impl AlertComparator {
+    /// Whether the value breaches the threshold
+    pub fn compare(&self, value: f64, threshold: f64) -> bool {
+        match self {
+            Self::GreaterThan => value > threshold,
+            Self::GreaterThanOrEqual => value >= threshold,
+            Self::LessThan => value < threshold,
+            Self::LessThanOrEqual => value <= threshold,
+            Self::Equal => value == threshold,
+            Self::NotEqual => value != threshold,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertSeverity {
+    Info,
+    #[default]
+    Warning,
+    Critical,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertState {
+    /// Threshold breached, but not for long enough to fire
+    Pending,
+    Firing,
+    Resolved,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    /// Name of the rule
+    pub rule: String,
+    /// Path of the metric that breached the threshold (e.g. "disks./.perc_used")
+    pub metric: String,
+    pub comparator: AlertComparator,
+    pub threshold: f64,
+    /// Threshold the metric must pass back over to resolve
+    pub clear_threshold: f64,
+    pub severity: AlertSeverity,
+    pub state: AlertState,
+    /// Latest value of the metric
+    pub value: f64,
+    /// When the metric first breached the threshold
+    pub started_at: SystemTime,
+    pub fired_at: Option<SystemTime>,
+    /// When a firing webhook was last sent
+    pub notified_at: Option<SystemTime>,
+    pub resolved_at: Option<SystemTime>,
+}
+
+/// Change in the state of an alert
+#[derive(Debug, Clone)]
+pub enum AlertEvent {
+    Firing(Alert),
+    Resolved(Alert),
+}
@@ -1,5 +1,6 @@
 pub type Percent = f32;
 pub type Bytes = u64;
 
+pub mod alerts;
 pub mod metrics;
 pub mod webhooks;
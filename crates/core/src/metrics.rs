@@ -1,5 +1,6 @@
 use crate::{Bytes, Percent};
 use serde::Serialize;
+use std::collections::BTreeMap;
 use std::time::{Duration, SystemTime};
 
 #[derive(Debug, Clone, Serialize)]
@@ -21,16 +22,338 @@ pub struct MemoryDetailedMetrics {
     pub free: Bytes,
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct SwapRateMetrics {
+    pub swapped_in: f64,
+    pub swapped_out: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SwapMetrics {
+    pub perc_used: Percent,
+    pub total: Bytes,
+    pub used: Bytes,
+    pub free: Bytes,
+    /// Total swapped in from disk since boot
+    pub swapped_in: Bytes,
+    /// Total swapped out to disk since boot
+    pub swapped_out: Bytes,
+    /// Per second rates since the previous capture
+    pub rates: Option<SwapRateMetrics>,
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct MemoryMetrics {
     pub perc_used: Percent,
     pub detailed: Option<MemoryDetailedMetrics>,
+    pub swap: Option<SwapMetrics>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiskInodeMetrics {
+    pub total: u64,
+    pub used: u64,
+    pub free: u64,
+    pub perc_used: Percent,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiskMetrics {
+    pub mount_point: String,
+    pub device: String,
+    pub fs_type: String,
+    pub total: Bytes,
+    pub used: Bytes,
+    pub free: Bytes,
+    pub perc_used: Percent,
+    pub inodes: Option<DiskInodeMetrics>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiskIoRateMetrics {
+    pub reads: f64,
+    pub writes: f64,
+    pub read_bytes: f64,
+    pub write_bytes: f64,
+    /// Average time in milliseconds for a read to be served, including time queued
+    pub read_await_ms: f64,
+    pub write_await_ms: f64,
+    /// Average number of requests in flight
+    pub queue_depth: f64,
+    /// Percent of time the device was busy
+    pub perc_util: Percent,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiskIoMetrics {
+    pub device: String,
+    /// Total reads completed since boot
+    pub reads: u64,
+    /// Total writes completed since boot
+    pub writes: u64,
+    pub read_bytes: Bytes,
+    pub write_bytes: Bytes,
+    /// Per second rates since the previous capture
+    pub rates: Option<DiskIoRateMetrics>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NetworkRateMetrics {
+    pub bytes_sent: f64,
+    pub bytes_recv: f64,
+    pub packets_sent: f64,
+    pub packets_recv: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NetworkInterfaceMetrics {
+    pub name: String,
+    pub bytes_sent: Bytes,
+    pub bytes_recv: Bytes,
+    pub packets_sent: u64,
+    pub packets_recv: u64,
+    pub errors_in: u64,
+    pub errors_out: u64,
+    pub drops_in: u64,
+    pub drops_out: u64,
+    /// Per second rates since the previous capture
+    pub rates: Option<NetworkRateMetrics>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LoadAverageMetrics {
+    pub one: f32,
+    pub five: f32,
+    pub fifteen: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CpuCountMetrics {
+    pub logical: u64,
+    pub physical: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SystemMetrics {
+    pub hostname: String,
+    pub os_name: String,
+    pub kernel_release: String,
+    /// Seconds since the system booted
+    pub uptime: Option<u64>,
+    pub boot_time: Option<SystemTime>,
+    pub load_average: Option<LoadAverageMetrics>,
+    pub cpu_count: CpuCountMetrics,
+}
+
+/// A temperature sensor, values are in degrees celsius
+#[derive(Debug, Clone, Serialize)]
+pub struct SensorMetrics {
+    pub label: String,
+    /// Sysfs entry the sensor was read from (e.g. 'thermal_zone0' or 'hwmon1/temp1'),
+    /// unlike the label this is unique
+    pub source: String,
+    pub current: f32,
+    pub high: Option<f32>,
+    pub critical: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessMetrics {
+    pub pid: u32,
+    pub name: String,
+    /// Only given when enabled, as arguments may contain secrets
+    pub cmdline: Option<String>,
+    pub user: Option<String>,
+    pub cpu_perc: Percent,
+    pub rss: Bytes,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessesMetrics {
+    pub total: u64,
+    /// Number of processes in each state (e.g. running, sleeping)
+    pub by_state: BTreeMap<String, u64>,
+    pub top_cpu: Vec<ProcessMetrics>,
+    pub top_memory: Vec<ProcessMetrics>,
 }
 
 #[derive(Debug, Clone, Serialize)]
+pub struct WatchedProcessMetrics {
+    pub name: String,
+    pub running: bool,
+    pub pids: Vec<u32>,
+    /// Seconds since the longest running matched process started
+    pub uptime: Option<u64>,
+    pub cpu_perc: Percent,
+    pub rss: Bytes,
+    /// Why the watch config is invalid, in which case no process is matched
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CgroupCpuMetrics {
+    pub usage_usec: u64,
+    pub user_usec: u64,
+    pub system_usec: u64,
+    pub nr_throttled: u64,
+    pub throttled_usec: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CgroupMemoryMetrics {
+    pub current: Bytes,
+    /// None when the cgroup has no memory limit
+    pub max: Option<Bytes>,
+    pub perc_used: Option<Percent>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CgroupIoMetrics {
+    pub rbytes: Bytes,
+    pub wbytes: Bytes,
+    pub rios: u64,
+    pub wios: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CgroupPidsMetrics {
+    pub current: u64,
+    /// None when the cgroup has no pids limit
+    pub max: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CgroupMetrics {
+    /// Path relative to the cgroup root
+    pub path: String,
+    pub cpu: Option<CgroupCpuMetrics>,
+    pub memory: Option<CgroupMemoryMetrics>,
+    pub io: Option<CgroupIoMetrics>,
+    pub pids: Option<CgroupPidsMetrics>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PressureStallMetrics {
+    /// Percent of time stalled, averaged over 10 seconds
+    pub avg10: Percent,
+    pub avg60: Percent,
+    pub avg300: Percent,
+    /// Total stall time in microseconds
+    pub total: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PressureResourceMetrics {
+    /// Some tasks were stalled
+    pub some: PressureStallMetrics,
+    /// All non-idle tasks were stalled
+    pub full: Option<PressureStallMetrics>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PressureMetrics {
+    pub cpu: Option<PressureResourceMetrics>,
+    pub memory: Option<PressureResourceMetrics>,
+    pub io: Option<PressureResourceMetrics>,
+}
+
+/// Value reported by a custom command
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum CustomValue {
+    Number(f64),
+    Bool(bool),
+    Text(String),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CustomCommandMetrics {
+    /// When the command was last run
+    pub captured_at: SystemTime,
+    pub values: BTreeMap<String, CustomValue>,
+    /// Why the last run failed, values will be empty
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TextfileMetrics {
+    /// File name, relative to the textfile directory
+    pub file: String,
+    /// When the file was last written to
+    pub modified: Option<SystemTime>,
+    pub values: BTreeMap<String, CustomValue>,
+    /// Why the file could not be read, values will be empty
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OutboxMetrics {
+    /// Webhooks waiting to be delivered
+    pub depth: u64,
+    /// Seconds since the oldest waiting webhook was queued
+    pub oldest_age: Option<u64>,
+}
+
+/// Metrics about the agent itself
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct AgentMetrics {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outbox: Option<OutboxMetrics>,
+}
+
+/// Metrics gathered by each collector, sections are absent when their collector is disabled
+#[derive(Debug, Clone, Serialize, Default)]
 pub struct Metrics {
-    pub cpu: CpuMetrics,
-    pub memory: MemoryMetrics,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu: Option<CpuMetrics>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<MemoryMetrics>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disks: Option<Vec<DiskMetrics>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disk_io: Option<Vec<DiskIoMetrics>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<Vec<NetworkInterfaceMetrics>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system: Option<SystemMetrics>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sensors: Option<Vec<SensorMetrics>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub processes: Option<ProcessesMetrics>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub watched: Option<Vec<WatchedProcessMetrics>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cgroups: Option<Vec<CgroupMetrics>>,
+    /// Also absent when the kernel does not support pressure stall information
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pressure: Option<PressureMetrics>,
+    /// Results of custom commands, by command name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub custom: Option<BTreeMap<String, CustomCommandMetrics>>,
+    /// Metrics read from files written by other programs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub textfile: Option<Vec<TextfileMetrics>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub agent: Option<AgentMetrics>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RollupValue {
+    pub min: f64,
+    pub avg: f64,
+    pub max: f64,
+}
+
+/// Numeric metrics summarised over an interval
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricsRollup {
+    pub start: SystemTime,
+    /// Length of the interval in seconds
+    pub interval: u64,
+    /// Number of captures summarised
+    pub samples: u64,
+    /// Values keyed by their path in the metrics (e.g. "memory.perc_used")
+    pub values: BTreeMap<String, RollupValue>,
 }
 
 #[derive(Debug, Clone, Serialize)]
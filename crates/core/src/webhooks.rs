@@ -1,16 +1,25 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::time::SystemTime;
 
-use crate::metrics::Metrics;
+use crate::alerts::Alert;
+use crate::metrics::{Metrics, WatchedProcessMetrics};
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum HookTypes {
-    #[serde(rename="ON_START")]
+    #[serde(rename = "ON_START")]
     OnStart,
-    #[serde(rename="PING")]
+    #[serde(rename = "ON_SHUTDOWN")]
+    OnShutdown,
+    #[serde(rename = "PING")]
     Ping,
-    #[serde(rename="METRICS")]
+    #[serde(rename = "METRICS")]
     Metrics,
+    #[serde(rename = "WATCHED_CHANGE")]
+    WatchedChange,
+    #[serde(rename = "ALERT_FIRING")]
+    AlertFiring,
+    #[serde(rename = "ALERT_RESOLVED")]
+    AlertResolved,
 }
 
 #[derive(Debug, Serialize)]
@@ -27,3 +36,19 @@ pub struct MetricsBody {
     pub hook_type: HookTypes,
     pub metrics: Metrics,
 }
+
+#[derive(Debug, Serialize)]
+pub struct WatchedChangeBody {
+    pub agent_id: String,
+    pub sent_at: SystemTime,
+    pub hook_type: HookTypes,
+    pub process: WatchedProcessMetrics,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AlertBody {
+    pub agent_id: String,
+    pub sent_at: SystemTime,
+    pub hook_type: HookTypes,
+    pub alert: Alert,
+}
@@ -8,6 +8,7 @@ use std::net::IpAddr;
 use std::pin::Pin;
 
 pub(crate) struct Client {
+    #[allow(dead_code)]
     pub authenticated: bool,
 }
 
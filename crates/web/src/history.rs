@@ -0,0 +1,120 @@
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct HistoryQuery {
+    /// Only return captures taken after this unix timestamp in seconds
+    pub since: Option<u64>,
+    /// Comma separated fields to return, nested fields are joined with '.' (e.g. "cpu,memory.perc_used")
+    pub fields: Option<String>,
+}
+
+impl HistoryQuery {
+    pub fn since(&self) -> Option<SystemTime> {
+        self.since.map(|v| UNIX_EPOCH + Duration::from_secs(v))
+    }
+    pub fn fields(&self) -> Vec<&str> {
+        split_fields(&self.fields)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct StoreQuery {
+    /// Interval of the rollups to return in seconds, every capture is returned when not given
+    pub resolution: Option<u64>,
+    /// Unix timestamp in seconds to return records from, defaults to an hour ago
+    pub from: Option<u64>,
+    /// Unix timestamp in seconds to return records until, defaults to now
+    pub to: Option<u64>,
+    /// Comma separated fields to return, nested fields are joined with '.' (e.g. "cpu,memory.perc_used")
+    pub fields: Option<String>,
+}
+
+impl StoreQuery {
+    /// Range of unix timestamps to return records for
+    pub fn range(&self) -> (u64, u64) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|v| v.as_secs())
+            .unwrap_or_default();
+        let to = self.to.unwrap_or(now);
+        (self.from.unwrap_or(to.saturating_sub(60 * 60)), to)
+    }
+    pub fn fields(&self) -> Vec<&str> {
+        split_fields(&self.fields)
+    }
+}
+
+fn split_fields(fields: &Option<String>) -> Vec<&str> {
+    match fields {
+        Some(v) => v
+            .split(',')
+            .map(str::trim)
+            .filter(|field| !field.is_empty())
+            .collect(),
+        None => vec![],
+    }
+}
+
+/// Copy a single field from the source into the selected values, keeping its nesting
+fn select_field(source: &Value, path: &str, selected: &mut Map<String, Value>) {
+    let (key, rest) = match path.split_once('.') {
+        Some((key, rest)) => (key, Some(rest)),
+        None => (path, None),
+    };
+    let value = match source.get(key) {
+        Some(v) => v,
+        None => return,
+    };
+    match rest {
+        None => {
+            selected.insert(key.to_string(), value.clone());
+        }
+        Some(rest) => {
+            let nested = selected
+                .entry(key.to_string())
+                .or_insert_with(|| Value::Object(Map::new()));
+            if let Value::Object(nested) = nested {
+                select_field(value, rest, nested);
+            }
+        }
+    }
+}
+
+/// Build the time series of captures, only including the given fields
+pub(crate) fn series(mut captures: Vec<Value>, fields: &[&str]) -> Vec<Value> {
+    for captured in captures.iter_mut() {
+        select_metrics(captured, fields);
+    }
+    captures
+}
+
+/// Only keep the given fields in a capture
+pub(crate) fn select_metrics(captured: &mut Value, fields: &[&str]) {
+    if fields.is_empty() {
+        return;
+    }
+    let mut selected = Map::new();
+    for field in fields {
+        select_field(&captured["metrics"], field, &mut selected);
+    }
+    captured["metrics"] = Value::Object(selected);
+}
+
+/// Only keep the given fields in a rollup, where values are keyed by their full path
+pub(crate) fn select_rollup(rollup: &mut Value, fields: &[&str]) {
+    if fields.is_empty() {
+        return;
+    }
+    if let Some(values) = rollup["values"].as_object_mut() {
+        values.retain(|key, _| {
+            fields.iter().any(|field| {
+                key == field
+                    || key
+                        .strip_prefix(field)
+                        .is_some_and(|rest| rest.starts_with('.'))
+            })
+        });
+    }
+}
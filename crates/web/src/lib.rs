@@ -2,12 +2,20 @@ use actix_web::{middleware::Logger, web, App, HttpServer};
 use agent_collector::CollectorState;
 use agent_config::types::Config;
 use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod};
+use std::future::Future;
 use std::sync::Arc;
 
 mod extractor;
+mod history;
+mod prometheus;
 mod routes;
 
-pub async fn run(config: &Config, collector: Arc<CollectorState>) -> std::io::Result<()> {
+/// Serve the API until shutdown completes, in-flight requests are finished before returning
+pub async fn run(
+    config: &Config,
+    collector: Arc<CollectorState>,
+    shutdown: impl Future<Output = ()>,
+) -> std::io::Result<()> {
     let config = config.clone();
     // Create the HTTP server
     let bind = (config.web.host.clone(), config.web.port);
@@ -27,16 +35,27 @@ pub async fn run(config: &Config, collector: Arc<CollectorState>) -> std::io::Re
         None => None,
     };
 
+    #[cfg(feature = "webhooks")]
+    let public_keys = web::Data::new(routes::PublicKeys::load(&config));
+
     let server = HttpServer::new(move || {
-        App::new()
+        let app = App::new()
             .wrap(Logger::default())
             .app_data(web::Data::from(collector.clone()))
-            .app_data(web::Data::new(config.clone()))
-            .service(routes::get_is_healthy)
+            .app_data(web::Data::new(config.clone()));
+        #[cfg(feature = "webhooks")]
+        let app = app
+            .app_data(public_keys.clone())
+            .service(routes::get_public_key);
+        app.service(routes::get_is_healthy)
             .service(routes::get_agent_id)
+            .service(routes::get_alerts)
             .service(
                 web::scope("/metrics")
                     .service(routes::get_all)
+                    .service(routes::get_prometheus)
+                    .service(routes::get_history)
+                    .service(routes::get_stored)
                     .service(
                         web::scope("/cpu").service(routes::get_cpu).service(
                             web::scope("/load")
@@ -49,20 +68,61 @@ pub async fn run(config: &Config, collector: Arc<CollectorState>) -> std::io::Re
                         web::scope("/memory")
                             .service(routes::get_memory)
                             .service(routes::get_memory_perc_used)
-                            .service(routes::get_memory_detailed),
-                    ),
+                            .service(routes::get_memory_detailed)
+                            .service(routes::get_memory_swap),
+                    )
+                    // '{mount:.*}' matches every path under /disks, so it is registered last & disk io
+                    // has its own scope, otherwise a mount point such as '/io' could not be fetched
+                    .service(
+                        web::scope("/disks")
+                            .service(routes::get_disks)
+                            .service(routes::get_disk),
+                    )
+                    .service(
+                        web::scope("/disk-io")
+                            .service(routes::get_disks_io)
+                            .service(routes::get_disk_io),
+                    )
+                    .service(
+                        web::scope("/network")
+                            .service(routes::get_network)
+                            .service(routes::get_network_interface),
+                    )
+                    .service(routes::get_system)
+                    .service(routes::get_sensors)
+                    .service(routes::get_processes)
+                    .service(routes::get_watched)
+                    .service(routes::get_cgroups)
+                    .service(routes::get_pressure)
+                    .service(
+                        web::scope("/custom")
+                            .service(routes::get_custom)
+                            .service(routes::get_custom_command),
+                    )
+                    .service(routes::get_textfile)
+                    .service(routes::get_agent),
             )
     });
 
-    // start the server
-    match ssl_builder {
+    // start the server, signals are handled by the caller
+    let server = match ssl_builder {
         Some(builder) => {
             log::info!("serving over HTTPS on: {bind:?}");
-            server.bind_openssl(bind, builder)?.run().await
+            server.bind_openssl(bind, builder)?
         }
         None => {
             log::info!("serving over HTTP on: {bind:?}");
-            server.bind(bind)?.run().await
+            server.bind(bind)?
         }
     }
+    .disable_signals()
+    .run();
+    let handle = server.handle();
+    let stop = async move {
+        shutdown.await;
+        log::info!("stopping web server");
+        handle.stop(true).await;
+    };
+    let (result, _) = futures::join!(server, stop);
+    result
 }
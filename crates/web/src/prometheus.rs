@@ -0,0 +1,841 @@
+use agent_core::metrics::{
+    CapturedMetrics, CgroupMetrics, CustomCommandMetrics, CustomValue, DiskIoMetrics, DiskMetrics,
+    NetworkInterfaceMetrics, PressureStallMetrics, SensorMetrics, TextfileMetrics,
+    WatchedProcessMetrics,
+};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+use std::time::UNIX_EPOCH;
+
+/// Content type of the Prometheus text exposition format
+pub(crate) const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Builds a Prometheus text exposition body
+struct Exposition {
+    body: String,
+    /// Names of the families written, each may only be written once
+    families: HashSet<String>,
+}
+
+impl Exposition {
+    fn new() -> Self {
+        Self {
+            body: String::new(),
+            families: HashSet::new(),
+        }
+    }
+    /// Write the HELP and TYPE lines for a metric family
+    fn family(&mut self, name: &str, help: &str, kind: &str) -> &mut Self {
+        self.families.insert(name.to_string());
+        writeln!(self.body, "# HELP {name} {help}").unwrap();
+        writeln!(self.body, "# TYPE {name} {kind}").unwrap();
+        self
+    }
+    /// Write a single sample, with optional labels
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Into<f64>) -> &mut Self {
+        self.body.push_str(name);
+        if !labels.is_empty() {
+            let labels = labels
+                .iter()
+                .map(|(k, v)| format!("{k}=\"{}\"", escape_label(v)))
+                .collect::<Vec<String>>()
+                .join(",");
+            write!(self.body, "{{{labels}}}").unwrap();
+        }
+        writeln!(self.body, " {}", value.into()).unwrap();
+        self
+    }
+    /// Write a sample whose name & labels are already formatted (e.g. 'backup_success{job="db"}')
+    fn verbatim(&mut self, key: &str, value: f64) -> &mut Self {
+        writeln!(self.body, "{key} {value}").unwrap();
+        self
+    }
+    /// Write a metric family that has a single unlabelled sample
+    fn gauge(&mut self, name: &str, help: &str, value: impl Into<f64>) -> &mut Self {
+        self.family(name, help, "gauge").sample(name, &[], value)
+    }
+    /// Write a metric family with a sample for each item, items without a value are skipped.
+    /// Only the first sample with each set of labels is kept, as duplicates fail the scrape
+    fn labelled<T>(
+        &mut self,
+        name: &str,
+        help: &str,
+        kind: &str,
+        items: &[T],
+        sample: impl Fn(&T) -> Option<(Labels, f64)>,
+    ) -> &mut Self {
+        let mut seen = HashSet::new();
+        let samples: Vec<(Labels, f64)> = items
+            .iter()
+            .filter_map(sample)
+            .filter(|(labels, _)| seen.insert(labels.clone()))
+            .collect();
+        if samples.is_empty() {
+            return self;
+        }
+        self.family(name, help, kind);
+        for (labels, value) in samples {
+            let labels: Vec<(&str, &str)> = labels.iter().map(|(k, v)| (*k, v.as_str())).collect();
+            self.sample(name, &labels, value);
+        }
+        self
+    }
+}
+
+/// Label names & values of a sample
+type Labels = Vec<(&'static str, String)>;
+
+/// Value of a custom metric as a sample, text values have none
+fn numeric(value: &CustomValue) -> Option<f64> {
+    match value {
+        CustomValue::Number(v) => Some(*v),
+        CustomValue::Bool(v) => Some(u8::from(*v).into()),
+        CustomValue::Text(_) => None,
+    }
+}
+
+/// Whether a name is a valid Prometheus metric name
+fn is_metric_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == ':')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+/// Escape a label value as required by the exposition format
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Render captured metrics in the Prometheus text exposition format
+pub(crate) fn render(captured: &CapturedMetrics) -> String {
+    let metrics = &captured.metrics;
+    let mut out = Exposition::new();
+
+    if let Some(load) = metrics.cpu.as_ref().and_then(|cpu| cpu.load.as_ref()) {
+        out.gauge(
+            "agent_cpu_load_average_percent",
+            "Average load across all cpu cores.",
+            load.average,
+        );
+        if let Some(per_core) = &load.per_core {
+            out.family("agent_cpu_load_percent", "Load of each cpu core.", "gauge");
+            for (core, load) in per_core.iter().enumerate() {
+                out.sample(
+                    "agent_cpu_load_percent",
+                    &[("core", &core.to_string())],
+                    *load,
+                );
+            }
+        }
+    }
+
+    if let Some(memory) = &metrics.memory {
+        out.gauge(
+            "agent_memory_used_percent",
+            "Percent of memory used.",
+            memory.perc_used,
+        );
+        if let Some(detailed) = &memory.detailed {
+            out.gauge(
+                "agent_memory_total_bytes",
+                "Total physical memory.",
+                detailed.total as f64,
+            )
+            .gauge(
+                "agent_memory_available_bytes",
+                "Memory available to start new applications.",
+                detailed.available as f64,
+            )
+            .gauge(
+                "agent_memory_used_bytes",
+                "Memory used.",
+                detailed.used as f64,
+            )
+            .gauge(
+                "agent_memory_free_bytes",
+                "Memory not being used at all.",
+                detailed.free as f64,
+            );
+        }
+
+        if let Some(swap) = &memory.swap {
+            out.gauge(
+                "agent_memory_swap_used_percent",
+                "Percent of swap used.",
+                swap.perc_used,
+            )
+            .gauge(
+                "agent_memory_swap_total_bytes",
+                "Total swap.",
+                swap.total as f64,
+            )
+            .gauge(
+                "agent_memory_swap_used_bytes",
+                "Swap used.",
+                swap.used as f64,
+            )
+            .gauge(
+                "agent_memory_swap_free_bytes",
+                "Swap not being used.",
+                swap.free as f64,
+            );
+            out.family(
+                "agent_memory_swapped_in_bytes_total",
+                "Memory swapped in from disk since boot.",
+                "counter",
+            )
+            .sample(
+                "agent_memory_swapped_in_bytes_total",
+                &[],
+                swap.swapped_in as f64,
+            );
+            out.family(
+                "agent_memory_swapped_out_bytes_total",
+                "Memory swapped out to disk since boot.",
+                "counter",
+            )
+            .sample(
+                "agent_memory_swapped_out_bytes_total",
+                &[],
+                swap.swapped_out as f64,
+            );
+        }
+    }
+
+    if let Some(disks) = &metrics.disks {
+        let labels = |disk: &DiskMetrics| -> Labels {
+            vec![
+                ("mount_point", disk.mount_point.clone()),
+                ("device", disk.device.clone()),
+                ("fs_type", disk.fs_type.clone()),
+            ]
+        };
+        out.labelled(
+            "agent_disk_total_bytes",
+            "Size of the filesystem.",
+            "gauge",
+            disks,
+            |disk| Some((labels(disk), disk.total as f64)),
+        )
+        .labelled(
+            "agent_disk_used_bytes",
+            "Space used on the filesystem.",
+            "gauge",
+            disks,
+            |disk| Some((labels(disk), disk.used as f64)),
+        )
+        .labelled(
+            "agent_disk_free_bytes",
+            "Space available on the filesystem.",
+            "gauge",
+            disks,
+            |disk| Some((labels(disk), disk.free as f64)),
+        )
+        .labelled(
+            "agent_disk_used_percent",
+            "Percent of the filesystem used.",
+            "gauge",
+            disks,
+            |disk| Some((labels(disk), disk.perc_used.into())),
+        )
+        .labelled(
+            "agent_disk_inodes_used",
+            "Inodes used on the filesystem.",
+            "gauge",
+            disks,
+            |disk| Some((labels(disk), disk.inodes.as_ref()?.used as f64)),
+        )
+        .labelled(
+            "agent_disk_inodes_free",
+            "Inodes available on the filesystem.",
+            "gauge",
+            disks,
+            |disk| Some((labels(disk), disk.inodes.as_ref()?.free as f64)),
+        )
+        .labelled(
+            "agent_disk_inodes_used_percent",
+            "Percent of inodes used on the filesystem.",
+            "gauge",
+            disks,
+            |disk| Some((labels(disk), disk.inodes.as_ref()?.perc_used.into())),
+        );
+    }
+
+    if let Some(network) = &metrics.network {
+        let labels = |interface: &NetworkInterfaceMetrics| -> Labels {
+            vec![("interface", interface.name.clone())]
+        };
+        out.labelled(
+            "agent_network_received_bytes_total",
+            "Bytes received by the interface since boot.",
+            "counter",
+            network,
+            |interface| Some((labels(interface), interface.bytes_recv as f64)),
+        )
+        .labelled(
+            "agent_network_sent_bytes_total",
+            "Bytes sent by the interface since boot.",
+            "counter",
+            network,
+            |interface| Some((labels(interface), interface.bytes_sent as f64)),
+        )
+        .labelled(
+            "agent_network_received_packets_total",
+            "Packets received by the interface since boot.",
+            "counter",
+            network,
+            |interface| Some((labels(interface), interface.packets_recv as f64)),
+        )
+        .labelled(
+            "agent_network_sent_packets_total",
+            "Packets sent by the interface since boot.",
+            "counter",
+            network,
+            |interface| Some((labels(interface), interface.packets_sent as f64)),
+        )
+        .labelled(
+            "agent_network_receive_errors_total",
+            "Errors receiving on the interface since boot.",
+            "counter",
+            network,
+            |interface| Some((labels(interface), interface.errors_in as f64)),
+        )
+        .labelled(
+            "agent_network_transmit_errors_total",
+            "Errors sending on the interface since boot.",
+            "counter",
+            network,
+            |interface| Some((labels(interface), interface.errors_out as f64)),
+        )
+        .labelled(
+            "agent_network_receive_drops_total",
+            "Incoming packets dropped by the interface since boot.",
+            "counter",
+            network,
+            |interface| Some((labels(interface), interface.drops_in as f64)),
+        )
+        .labelled(
+            "agent_network_transmit_drops_total",
+            "Outgoing packets dropped by the interface since boot.",
+            "counter",
+            network,
+            |interface| Some((labels(interface), interface.drops_out as f64)),
+        );
+    }
+
+    if let Some(system) = &metrics.system {
+        out.labelled(
+            "agent_system_info",
+            "Host details, the value is always 1.",
+            "gauge",
+            &[system],
+            |system| {
+                let labels = vec![
+                    ("hostname", system.hostname.clone()),
+                    ("os_name", system.os_name.clone()),
+                    ("kernel_release", system.kernel_release.clone()),
+                ];
+                Some((labels, 1.0))
+            },
+        );
+        if let Some(load) = &system.load_average {
+            out.labelled(
+                "agent_system_load_average",
+                "Average number of runnable processes over the period.",
+                "gauge",
+                &[("1m", load.one), ("5m", load.five), ("15m", load.fifteen)],
+                |(period, load)| Some((vec![("period", period.to_string())], (*load).into())),
+            );
+        }
+        if let Some(uptime) = system.uptime {
+            out.gauge(
+                "agent_system_uptime_seconds",
+                "Time since the host booted.",
+                uptime as f64,
+            );
+        }
+        if let Some(boot_time) = system
+            .boot_time
+            .and_then(|v| v.duration_since(UNIX_EPOCH).ok())
+        {
+            out.gauge(
+                "agent_system_boot_time_seconds",
+                "Unix time the host booted.",
+                boot_time.as_secs() as f64,
+            );
+        }
+        out.labelled(
+            "agent_system_cpus",
+            "Number of cpus, logical cpus include hyperthreads.",
+            "gauge",
+            &[
+                ("logical", system.cpu_count.logical),
+                ("physical", system.cpu_count.physical),
+            ],
+            |(kind, count)| Some((vec![("kind", kind.to_string())], *count as f64)),
+        );
+    }
+
+    if let Some(sensors) = &metrics.sensors {
+        // labels are not unique (e.g. several 'acpitz' zones), so the source is included
+        let labels = |sensor: &SensorMetrics| -> Labels {
+            vec![
+                ("sensor", sensor.label.clone()),
+                ("source", sensor.source.clone()),
+            ]
+        };
+        out.labelled(
+            "agent_sensor_temperature_celsius",
+            "Current temperature of the sensor.",
+            "gauge",
+            sensors,
+            |sensor| Some((labels(sensor), sensor.current.into())),
+        )
+        .labelled(
+            "agent_sensor_high_celsius",
+            "Temperature the sensor is considered hot at.",
+            "gauge",
+            sensors,
+            |sensor| Some((labels(sensor), sensor.high?.into())),
+        )
+        .labelled(
+            "agent_sensor_critical_celsius",
+            "Temperature the sensor is considered critical at.",
+            "gauge",
+            sensors,
+            |sensor| Some((labels(sensor), sensor.critical?.into())),
+        );
+    }
+
+    if let Some(processes) = &metrics.processes {
+        out.gauge(
+            "agent_processes",
+            "Number of processes running on the host.",
+            processes.total as f64,
+        );
+        let by_state: Vec<(&String, &u64)> = processes.by_state.iter().collect();
+        out.labelled(
+            "agent_processes_by_state",
+            "Number of processes in each state.",
+            "gauge",
+            &by_state,
+            |(state, count)| Some((vec![("state", state.to_string())], **count as f64)),
+        );
+    }
+
+    if let Some(watched) = &metrics.watched {
+        let labels =
+            |process: &WatchedProcessMetrics| -> Labels { vec![("name", process.name.clone())] };
+        out.labelled(
+            "agent_watched_running",
+            "Whether the watched process is running, 1 when running.",
+            "gauge",
+            watched,
+            |process| Some((labels(process), u8::from(process.running).into())),
+        )
+        .labelled(
+            "agent_watched_processes",
+            "Number of processes matching the watched process.",
+            "gauge",
+            watched,
+            |process| Some((labels(process), process.pids.len() as f64)),
+        )
+        .labelled(
+            "agent_watched_uptime_seconds",
+            "Time since the oldest matching process started.",
+            "gauge",
+            watched,
+            |process| Some((labels(process), process.uptime? as f64)),
+        )
+        .labelled(
+            "agent_watched_cpu_percent",
+            "Cpu used by the matching processes.",
+            "gauge",
+            watched,
+            |process| Some((labels(process), process.cpu_perc.into())),
+        )
+        .labelled(
+            "agent_watched_rss_bytes",
+            "Resident memory of the matching processes.",
+            "gauge",
+            watched,
+            |process| Some((labels(process), process.rss as f64)),
+        );
+    }
+
+    if let Some(cgroups) = &metrics.cgroups {
+        let labels = |cgroup: &CgroupMetrics| -> Labels { vec![("path", cgroup.path.clone())] };
+        out.labelled(
+            "agent_cgroup_cpu_seconds_total",
+            "Cpu time used by the cgroup.",
+            "counter",
+            cgroups,
+            |cgroup| Some((labels(cgroup), cgroup.cpu.as_ref()?.usage_usec as f64 / 1e6)),
+        )
+        .labelled(
+            "agent_cgroup_cpu_user_seconds_total",
+            "Cpu time used by the cgroup in user mode.",
+            "counter",
+            cgroups,
+            |cgroup| Some((labels(cgroup), cgroup.cpu.as_ref()?.user_usec as f64 / 1e6)),
+        )
+        .labelled(
+            "agent_cgroup_cpu_system_seconds_total",
+            "Cpu time used by the cgroup in kernel mode.",
+            "counter",
+            cgroups,
+            |cgroup| {
+                Some((
+                    labels(cgroup),
+                    cgroup.cpu.as_ref()?.system_usec as f64 / 1e6,
+                ))
+            },
+        )
+        .labelled(
+            "agent_cgroup_cpu_throttled_periods_total",
+            "Periods the cgroup was throttled for reaching its cpu limit.",
+            "counter",
+            cgroups,
+            |cgroup| Some((labels(cgroup), cgroup.cpu.as_ref()?.nr_throttled as f64)),
+        )
+        .labelled(
+            "agent_cgroup_cpu_throttled_seconds_total",
+            "Time the cgroup was throttled for reaching its cpu limit.",
+            "counter",
+            cgroups,
+            |cgroup| {
+                Some((
+                    labels(cgroup),
+                    cgroup.cpu.as_ref()?.throttled_usec as f64 / 1e6,
+                ))
+            },
+        )
+        .labelled(
+            "agent_cgroup_memory_bytes",
+            "Memory used by the cgroup.",
+            "gauge",
+            cgroups,
+            |cgroup| Some((labels(cgroup), cgroup.memory.as_ref()?.current as f64)),
+        )
+        .labelled(
+            "agent_cgroup_memory_max_bytes",
+            "Memory limit of the cgroup, missing when unlimited.",
+            "gauge",
+            cgroups,
+            |cgroup| Some((labels(cgroup), cgroup.memory.as_ref()?.max? as f64)),
+        )
+        .labelled(
+            "agent_cgroup_memory_used_percent",
+            "Percent of the memory limit used by the cgroup.",
+            "gauge",
+            cgroups,
+            |cgroup| Some((labels(cgroup), cgroup.memory.as_ref()?.perc_used?.into())),
+        )
+        .labelled(
+            "agent_cgroup_io_read_bytes_total",
+            "Bytes read by the cgroup.",
+            "counter",
+            cgroups,
+            |cgroup| Some((labels(cgroup), cgroup.io.as_ref()?.rbytes as f64)),
+        )
+        .labelled(
+            "agent_cgroup_io_written_bytes_total",
+            "Bytes written by the cgroup.",
+            "counter",
+            cgroups,
+            |cgroup| Some((labels(cgroup), cgroup.io.as_ref()?.wbytes as f64)),
+        )
+        .labelled(
+            "agent_cgroup_io_reads_total",
+            "Read operations by the cgroup.",
+            "counter",
+            cgroups,
+            |cgroup| Some((labels(cgroup), cgroup.io.as_ref()?.rios as f64)),
+        )
+        .labelled(
+            "agent_cgroup_io_writes_total",
+            "Write operations by the cgroup.",
+            "counter",
+            cgroups,
+            |cgroup| Some((labels(cgroup), cgroup.io.as_ref()?.wios as f64)),
+        )
+        .labelled(
+            "agent_cgroup_pids",
+            "Number of processes in the cgroup.",
+            "gauge",
+            cgroups,
+            |cgroup| Some((labels(cgroup), cgroup.pids.as_ref()?.current as f64)),
+        )
+        .labelled(
+            "agent_cgroup_pids_max",
+            "Process limit of the cgroup, missing when unlimited.",
+            "gauge",
+            cgroups,
+            |cgroup| Some((labels(cgroup), cgroup.pids.as_ref()?.max? as f64)),
+        );
+    }
+
+    if let Some(pressure) = &metrics.pressure {
+        let stalls: Vec<(&str, &str, &PressureStallMetrics)> = [
+            ("cpu", &pressure.cpu),
+            ("memory", &pressure.memory),
+            ("io", &pressure.io),
+        ]
+        .into_iter()
+        .filter_map(|(resource, stalls)| Some((resource, stalls.as_ref()?)))
+        .flat_map(|(resource, stalls)| {
+            let full = stalls.full.as_ref().map(|full| (resource, "full", full));
+            std::iter::once((resource, "some", &stalls.some)).chain(full)
+        })
+        .collect();
+        let labels = |resource: &str, kind: &str| -> Labels {
+            vec![
+                ("resource", resource.to_string()),
+                ("kind", kind.to_string()),
+            ]
+        };
+        let windows: Vec<(&str, &str, &str, f32)> = stalls
+            .iter()
+            .flat_map(|(resource, kind, stall)| {
+                [
+                    (*resource, *kind, "10s", stall.avg10),
+                    (*resource, *kind, "60s", stall.avg60),
+                    (*resource, *kind, "300s", stall.avg300),
+                ]
+            })
+            .collect();
+        out.labelled(
+            "agent_pressure_stall_percent",
+            "Percent of time tasks were stalled on the resource, averaged over the window.",
+            "gauge",
+            &windows,
+            |(resource, kind, window, value)| {
+                let mut labels = labels(resource, kind);
+                labels.push(("window", window.to_string()));
+                Some((labels, (*value).into()))
+            },
+        )
+        .labelled(
+            "agent_pressure_stall_seconds_total",
+            "Time tasks were stalled on the resource since boot.",
+            "counter",
+            &stalls,
+            |(resource, kind, stall)| Some((labels(resource, kind), stall.total as f64 / 1e6)),
+        );
+    }
+
+    if let Some(disk_io) = &metrics.disk_io {
+        let labels = |device: &DiskIoMetrics| -> Labels { vec![("device", device.device.clone())] };
+        out.labelled(
+            "agent_disk_io_reads_total",
+            "Reads completed by the device since boot.",
+            "counter",
+            disk_io,
+            |device| Some((labels(device), device.reads as f64)),
+        )
+        .labelled(
+            "agent_disk_io_writes_total",
+            "Writes completed by the device since boot.",
+            "counter",
+            disk_io,
+            |device| Some((labels(device), device.writes as f64)),
+        )
+        .labelled(
+            "agent_disk_io_read_bytes_total",
+            "Bytes read from the device since boot.",
+            "counter",
+            disk_io,
+            |device| Some((labels(device), device.read_bytes as f64)),
+        )
+        .labelled(
+            "agent_disk_io_written_bytes_total",
+            "Bytes written to the device since boot.",
+            "counter",
+            disk_io,
+            |device| Some((labels(device), device.write_bytes as f64)),
+        )
+        .labelled(
+            "agent_disk_io_read_await_milliseconds",
+            "Average time for a read to be served since the previous capture, including time queued.",
+            "gauge",
+            disk_io,
+            |device| Some((labels(device), device.rates.as_ref()?.read_await_ms)),
+        )
+        .labelled(
+            "agent_disk_io_write_await_milliseconds",
+            "Average time for a write to be served since the previous capture, including time queued.",
+            "gauge",
+            disk_io,
+            |device| Some((labels(device), device.rates.as_ref()?.write_await_ms)),
+        )
+        .labelled(
+            "agent_disk_io_queue_depth",
+            "Average number of requests in flight since the previous capture.",
+            "gauge",
+            disk_io,
+            |device| Some((labels(device), device.rates.as_ref()?.queue_depth)),
+        )
+        .labelled(
+            "agent_disk_io_busy_percent",
+            "Percent of time the device was busy since the previous capture.",
+            "gauge",
+            disk_io,
+            |device| Some((labels(device), device.rates.as_ref()?.perc_util.into())),
+        );
+    }
+
+    if let Some(custom) = &metrics.custom {
+        let commands: Vec<(&String, &CustomCommandMetrics)> = custom.iter().collect();
+        let values: Vec<(&String, &String, f64)> = commands
+            .iter()
+            .flat_map(|(command, result)| {
+                result
+                    .values
+                    .iter()
+                    .filter_map(move |(key, value)| Some((*command, key, numeric(value)?)))
+            })
+            .collect();
+        out.labelled(
+            "agent_custom_value",
+            "Numeric values reported by custom commands, booleans are 0 or 1.",
+            "gauge",
+            &values,
+            |(command, key, value)| {
+                let labels = vec![("command", command.to_string()), ("key", key.to_string())];
+                Some((labels, *value))
+            },
+        )
+        .labelled(
+            "agent_custom_success",
+            "Whether the last run of the custom command succeeded, 1 when it did.",
+            "gauge",
+            &commands,
+            |(command, result)| {
+                let labels = vec![("command", command.to_string())];
+                Some((labels, u8::from(result.error.is_none()).into()))
+            },
+        )
+        .labelled(
+            "agent_custom_last_run_timestamp_seconds",
+            "Unix time the custom command was last run.",
+            "gauge",
+            &commands,
+            |(command, result)| {
+                let ran_at = result.captured_at.duration_since(UNIX_EPOCH).ok()?;
+                Some((vec![("command", command.to_string())], ran_at.as_secs_f64()))
+            },
+        );
+    }
+
+    if let Some(textfiles) = &metrics.textfile {
+        let json_values: Vec<(&str, &str, f64)> = textfiles
+            .iter()
+            .filter(|textfile| textfile.file.ends_with(".json"))
+            .flat_map(|textfile| {
+                textfile.values.iter().filter_map(|(key, value)| {
+                    Some((textfile.file.as_str(), key.as_str(), numeric(value)?))
+                })
+            })
+            .collect();
+        out.labelled(
+            "agent_textfile_value",
+            "Numeric values read from JSON textfiles, booleans are 0 or 1.",
+            "gauge",
+            &json_values,
+            |(file, key, value)| {
+                let labels = vec![("file", file.to_string()), ("key", key.to_string())];
+                Some((labels, *value))
+            },
+        )
+        .labelled(
+            "agent_textfile_success",
+            "Whether the textfile was read & parsed, 1 when it was.",
+            "gauge",
+            textfiles,
+            |textfile| {
+                let labels = vec![("file", textfile.file.clone())];
+                Some((labels, u8::from(textfile.error.is_none()).into()))
+            },
+        )
+        .labelled(
+            "agent_textfile_modified_timestamp_seconds",
+            "Unix time the textfile was last written to.",
+            "gauge",
+            textfiles,
+            |textfile| {
+                let modified = textfile.modified?.duration_since(UNIX_EPOCH).ok()?;
+                Some((
+                    vec![("file", textfile.file.clone())],
+                    modified.as_secs_f64(),
+                ))
+            },
+        );
+    }
+
+    if let Some(outbox) = metrics
+        .agent
+        .as_ref()
+        .and_then(|agent| agent.outbox.as_ref())
+    {
+        out.gauge(
+            "agent_outbox_depth",
+            "Webhooks waiting in the outbox to be delivered.",
+            outbox.depth as f64,
+        );
+        if let Some(oldest_age) = outbox.oldest_age {
+            out.gauge(
+                "agent_outbox_oldest_age_seconds",
+                "Time since the oldest webhook waiting in the outbox was queued.",
+                oldest_age as f64,
+            );
+        }
+    }
+
+    // written last, so families the agent already wrote are known
+    if let Some(textfiles) = &metrics.textfile {
+        for (name, samples) in textfile_families(textfiles) {
+            if out.families.contains(name) {
+                log::warn!("skipping textfile metric '{name}' as the agent already exports it");
+                continue;
+            }
+            out.family(name, "Read from the textfile directory.", "untyped");
+            for (key, value) in samples {
+                out.verbatim(key, value);
+            }
+        }
+    }
+
+    out.body
+}
+
+/// Samples from '.prom' textfiles, grouped by name across files so each family is written once.
+/// Samples with the same name & labels are only kept once, as duplicates fail the scrape
+fn textfile_families(textfiles: &[TextfileMetrics]) -> BTreeMap<&str, Vec<(&str, f64)>> {
+    let mut families: BTreeMap<&str, Vec<(&str, f64)>> = BTreeMap::new();
+    for textfile in textfiles {
+        if textfile.file.ends_with(".json") {
+            continue;
+        }
+        for (key, value) in &textfile.values {
+            let value = match numeric(value) {
+                Some(v) => v,
+                None => continue,
+            };
+            let name = key.split('{').next().unwrap_or_default();
+            if !is_metric_name(name) {
+                log::debug!("skipping textfile sample '{key}' as its name is invalid");
+                continue;
+            }
+            let samples = families.entry(name).or_default();
+            if !samples.iter().any(|(existing, _)| *existing == key) {
+                samples.push((key, value));
+            }
+        }
+    }
+    families
+}
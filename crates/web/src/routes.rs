@@ -1,9 +1,32 @@
-use actix_web::{get, web, web::Json};
-use agent_collector::CollectorState;
+use actix_web::{
+    error::{ErrorBadRequest, ErrorInternalServerError, ErrorNotFound},
+    get, web,
+    web::Json,
+    HttpResponse,
+};
+use agent_collector::{CollectorState, StoreError};
 use agent_config::types::Config;
+use agent_core::alerts::Alert;
 use agent_core::metrics;
+#[cfg(feature = "webhooks")]
+use openssl::pkey::PKey;
+use std::collections::BTreeMap;
+#[cfg(feature = "webhooks")]
+use std::fs::read;
 
 use crate::extractor::Client;
+use crate::history::{self, HistoryQuery, StoreQuery};
+use crate::prometheus;
+
+/// Unwrap a metrics section, which is absent when its collector is disabled or failed
+fn section<T>(section: Option<T>) -> actix_web::Result<T> {
+    section.ok_or_else(|| ErrorNotFound("metrics unavailable"))
+}
+
+/// Latest metrics, capturing off the async workers if the cache is old
+async fn latest(collector: &web::Data<CollectorState>) -> metrics::CapturedMetrics {
+    CollectorState::metrics_async(collector.clone().into_inner()).await
+}
 
 #[get("/is-healthy")]
 pub(crate) async fn get_is_healthy() -> actix_web::Result<String> {
@@ -18,21 +41,116 @@ pub(crate) async fn get_agent_id(
     Ok(config.id.clone())
 }
 
+/// PEM encoded public keys of the Ed25519 keys webhooks are signed with, loaded once at startup
+#[cfg(feature = "webhooks")]
+pub(crate) struct PublicKeys(Vec<u8>);
+
+#[cfg(feature = "webhooks")]
+impl PublicKeys {
+    /// Keys that can not be loaded are left out, as hooks using them are sent unsigned
+    pub(crate) fn load(config: &Config) -> Self {
+        let mut pem = vec![];
+        for path in config.webhooks.signing_keys() {
+            let public_key = read(&path)
+                .ok()
+                .and_then(|v| PKey::private_key_from_pem(&v).ok())
+                .and_then(|key| key.public_key_to_pem().ok());
+            match public_key {
+                Some(v) => pem.extend(v),
+                None => log::error!("unable to load signing key '{}'", path.display()),
+            }
+        }
+        Self(pem)
+    }
+}
+
+/// Public keys of the Ed25519 keys webhooks are signed with,
+/// not authenticated so receivers can fetch them
+#[cfg(feature = "webhooks")]
+#[get("/public-key")]
+pub(crate) async fn get_public_key(keys: web::Data<PublicKeys>) -> actix_web::Result<HttpResponse> {
+    if keys.0.is_empty() {
+        return Err(ErrorNotFound("no signing key configured"));
+    }
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-pem-file")
+        .body(keys.0.clone()))
+}
+
 #[get("/")]
 pub(crate) async fn get_all(
     _client: Client,
     collector: web::Data<CollectorState>,
 ) -> actix_web::Result<Json<metrics::Metrics>> {
-    let captured_metrics = collector.metrics();
+    let captured_metrics = latest(&collector).await;
     Ok(Json(captured_metrics.metrics))
 }
 
+#[get("/prometheus")]
+pub(crate) async fn get_prometheus(
+    _client: Client,
+    collector: web::Data<CollectorState>,
+) -> actix_web::Result<HttpResponse> {
+    let captured_metrics = latest(&collector).await;
+    Ok(HttpResponse::Ok()
+        .content_type(prometheus::CONTENT_TYPE)
+        .body(prometheus::render(&captured_metrics)))
+}
+
+#[get("/alerts")]
+pub(crate) async fn get_alerts(
+    _client: Client,
+    collector: web::Data<CollectorState>,
+) -> actix_web::Result<Json<Vec<Alert>>> {
+    Ok(Json(collector.alerts()))
+}
+
+#[get("/history")]
+pub(crate) async fn get_history(
+    _client: Client,
+    collector: web::Data<CollectorState>,
+    query: web::Query<HistoryQuery>,
+) -> actix_web::Result<Json<Vec<serde_json::Value>>> {
+    let captures = collector
+        .history(query.since())
+        .ok_or_else(|| ErrorNotFound("metrics history disabled"))?;
+    Ok(Json(history::series(captures, &query.fields())))
+}
+
+#[get("/store")]
+pub(crate) async fn get_stored(
+    _client: Client,
+    collector: web::Data<CollectorState>,
+    query: web::Query<StoreQuery>,
+) -> actix_web::Result<Json<Vec<serde_json::Value>>> {
+    let (from, to) = query.range();
+    let mut records = match collector.stored(query.resolution, from, to) {
+        None => return Err(ErrorNotFound("metrics store disabled")),
+        Some(Err(StoreError::UnknownResolution)) => {
+            return Err(ErrorBadRequest("no rollups stored for resolution"))
+        }
+        Some(Err(StoreError::Io(err))) => {
+            log::error!("unable to read stored metrics due to '{err}'");
+            return Err(ErrorInternalServerError("unable to read stored metrics"));
+        }
+        Some(Ok(v)) => v,
+    };
+    let fields = query.fields();
+    for record in records.iter_mut() {
+        match query.resolution {
+            Some(_) => history::select_rollup(record, &fields),
+            None => history::select_metrics(record, &fields),
+        }
+    }
+    Ok(Json(records))
+}
+
 #[get("/")]
 pub(crate) async fn get_cpu(
     _client: Client,
     collector: web::Data<CollectorState>,
 ) -> actix_web::Result<Json<metrics::CpuMetrics>> {
-    let cpu_metrics = collector.metrics().metrics.cpu;
+    let cpu_metrics = section(latest(&collector).await.metrics.cpu)?;
     Ok(Json(cpu_metrics))
 }
 
@@ -41,7 +159,7 @@ pub(crate) async fn get_cpu_load(
     _client: Client,
     collector: web::Data<CollectorState>,
 ) -> actix_web::Result<Json<metrics::CpuLoadMetrics>> {
-    let load = collector.metrics().metrics.cpu.load.unwrap();
+    let load = section(section(latest(&collector).await.metrics.cpu)?.load)?;
     Ok(Json(load))
 }
 
@@ -50,7 +168,7 @@ pub(crate) async fn get_cpu_load_average(
     _client: Client,
     collector: web::Data<CollectorState>,
 ) -> actix_web::Result<Json<agent_core::Percent>> {
-    let load = collector.metrics().metrics.cpu.load.unwrap();
+    let load = section(section(latest(&collector).await.metrics.cpu)?.load)?;
     Ok(Json(load.average))
 }
 
@@ -59,8 +177,8 @@ pub(crate) async fn get_cpu_load_per_core(
     _client: Client,
     collector: web::Data<CollectorState>,
 ) -> actix_web::Result<Json<Vec<agent_core::Percent>>> {
-    let load = collector.metrics().metrics.cpu.load.unwrap();
-    Ok(Json(load.per_core.unwrap()))
+    let load = section(section(latest(&collector).await.metrics.cpu)?.load)?;
+    Ok(Json(section(load.per_core)?))
 }
 
 #[get("/")]
@@ -68,7 +186,7 @@ pub(crate) async fn get_memory(
     _client: Client,
     collector: web::Data<CollectorState>,
 ) -> actix_web::Result<Json<metrics::MemoryMetrics>> {
-    let memory_metrics = collector.metrics().metrics.memory;
+    let memory_metrics = section(latest(&collector).await.metrics.memory)?;
     Ok(Json(memory_metrics))
 }
 
@@ -77,7 +195,7 @@ pub(crate) async fn get_memory_perc_used(
     _client: Client,
     collector: web::Data<CollectorState>,
 ) -> actix_web::Result<Json<agent_core::Percent>> {
-    let memory_metrics = collector.metrics().metrics.memory;
+    let memory_metrics = section(latest(&collector).await.metrics.memory)?;
     Ok(Json(memory_metrics.perc_used))
 }
 
@@ -86,6 +204,180 @@ pub(crate) async fn get_memory_detailed(
     _client: Client,
     collector: web::Data<CollectorState>,
 ) -> actix_web::Result<Json<metrics::MemoryDetailedMetrics>> {
-    let memory_metrics = collector.metrics().metrics.memory;
-    Ok(Json(memory_metrics.detailed.unwrap()))
+    let memory_metrics = section(latest(&collector).await.metrics.memory)?;
+    Ok(Json(section(memory_metrics.detailed)?))
+}
+
+#[get("/swap")]
+pub(crate) async fn get_memory_swap(
+    _client: Client,
+    collector: web::Data<CollectorState>,
+) -> actix_web::Result<Json<metrics::SwapMetrics>> {
+    let memory_metrics = section(latest(&collector).await.metrics.memory)?;
+    Ok(Json(section(memory_metrics.swap)?))
+}
+
+#[get("/")]
+pub(crate) async fn get_disks(
+    _client: Client,
+    collector: web::Data<CollectorState>,
+) -> actix_web::Result<Json<Vec<metrics::DiskMetrics>>> {
+    let disk_metrics = section(latest(&collector).await.metrics.disks)?;
+    Ok(Json(disk_metrics))
+}
+
+#[get("/")]
+pub(crate) async fn get_disks_io(
+    _client: Client,
+    collector: web::Data<CollectorState>,
+) -> actix_web::Result<Json<Vec<metrics::DiskIoMetrics>>> {
+    let disk_io_metrics = section(latest(&collector).await.metrics.disk_io)?;
+    Ok(Json(disk_io_metrics))
+}
+
+#[get("/{device}")]
+pub(crate) async fn get_disk_io(
+    _client: Client,
+    collector: web::Data<CollectorState>,
+    device: web::Path<String>,
+) -> actix_web::Result<Json<metrics::DiskIoMetrics>> {
+    section(latest(&collector).await.metrics.disk_io)?
+        .into_iter()
+        .find(|disk| disk.device == *device)
+        .map(Json)
+        .ok_or_else(|| ErrorNotFound("unknown block device"))
+}
+
+#[get("/{mount:.*}")]
+pub(crate) async fn get_disk(
+    _client: Client,
+    collector: web::Data<CollectorState>,
+    mount: web::Path<String>,
+) -> actix_web::Result<Json<metrics::DiskMetrics>> {
+    // mount points are given without the leading '/', an encoded '/' is also accepted
+    let mount = mount.replace("%2F", "/").replace("%2f", "/");
+    let mount = match mount.starts_with('/') {
+        true => mount,
+        false => format!("/{mount}"),
+    };
+    section(latest(&collector).await.metrics.disks)?
+        .into_iter()
+        .find(|disk| disk.mount_point == mount)
+        .map(Json)
+        .ok_or_else(|| ErrorNotFound("unknown mount point"))
+}
+
+#[get("/")]
+pub(crate) async fn get_network(
+    _client: Client,
+    collector: web::Data<CollectorState>,
+) -> actix_web::Result<Json<Vec<metrics::NetworkInterfaceMetrics>>> {
+    let network_metrics = section(latest(&collector).await.metrics.network)?;
+    Ok(Json(network_metrics))
+}
+
+#[get("/{iface}")]
+pub(crate) async fn get_network_interface(
+    _client: Client,
+    collector: web::Data<CollectorState>,
+    iface: web::Path<String>,
+) -> actix_web::Result<Json<metrics::NetworkInterfaceMetrics>> {
+    section(latest(&collector).await.metrics.network)?
+        .into_iter()
+        .find(|interface| interface.name == *iface)
+        .map(Json)
+        .ok_or_else(|| ErrorNotFound("unknown network interface"))
+}
+
+#[get("/system")]
+pub(crate) async fn get_system(
+    _client: Client,
+    collector: web::Data<CollectorState>,
+) -> actix_web::Result<Json<metrics::SystemMetrics>> {
+    let system_metrics = section(latest(&collector).await.metrics.system)?;
+    Ok(Json(system_metrics))
+}
+
+#[get("/sensors")]
+pub(crate) async fn get_sensors(
+    _client: Client,
+    collector: web::Data<CollectorState>,
+) -> actix_web::Result<Json<Vec<metrics::SensorMetrics>>> {
+    let sensor_metrics = section(latest(&collector).await.metrics.sensors)?;
+    Ok(Json(sensor_metrics))
+}
+
+#[get("/processes")]
+pub(crate) async fn get_processes(
+    _client: Client,
+    collector: web::Data<CollectorState>,
+) -> actix_web::Result<Json<metrics::ProcessesMetrics>> {
+    let process_metrics = section(latest(&collector).await.metrics.processes)?;
+    Ok(Json(process_metrics))
+}
+
+#[get("/watched")]
+pub(crate) async fn get_watched(
+    _client: Client,
+    collector: web::Data<CollectorState>,
+) -> actix_web::Result<Json<Vec<metrics::WatchedProcessMetrics>>> {
+    let watched_metrics = section(latest(&collector).await.metrics.watched)?;
+    Ok(Json(watched_metrics))
+}
+
+#[get("/cgroups")]
+pub(crate) async fn get_cgroups(
+    _client: Client,
+    collector: web::Data<CollectorState>,
+) -> actix_web::Result<Json<Vec<metrics::CgroupMetrics>>> {
+    let cgroup_metrics = section(latest(&collector).await.metrics.cgroups)?;
+    Ok(Json(cgroup_metrics))
+}
+
+#[get("/pressure")]
+pub(crate) async fn get_pressure(
+    _client: Client,
+    collector: web::Data<CollectorState>,
+) -> actix_web::Result<Json<metrics::PressureMetrics>> {
+    let pressure_metrics = section(latest(&collector).await.metrics.pressure)?;
+    Ok(Json(pressure_metrics))
+}
+
+#[get("/")]
+pub(crate) async fn get_custom(
+    _client: Client,
+    collector: web::Data<CollectorState>,
+) -> actix_web::Result<Json<BTreeMap<String, metrics::CustomCommandMetrics>>> {
+    let custom_metrics = section(latest(&collector).await.metrics.custom)?;
+    Ok(Json(custom_metrics))
+}
+
+#[get("/{name}")]
+pub(crate) async fn get_custom_command(
+    _client: Client,
+    collector: web::Data<CollectorState>,
+    name: web::Path<String>,
+) -> actix_web::Result<Json<metrics::CustomCommandMetrics>> {
+    section(latest(&collector).await.metrics.custom)?
+        .remove(name.as_str())
+        .map(Json)
+        .ok_or_else(|| ErrorNotFound("unknown custom command"))
+}
+
+#[get("/textfile")]
+pub(crate) async fn get_textfile(
+    _client: Client,
+    collector: web::Data<CollectorState>,
+) -> actix_web::Result<Json<Vec<metrics::TextfileMetrics>>> {
+    let textfile_metrics = section(latest(&collector).await.metrics.textfile)?;
+    Ok(Json(textfile_metrics))
+}
+
+#[get("/agent")]
+pub(crate) async fn get_agent(
+    _client: Client,
+    collector: web::Data<CollectorState>,
+) -> actix_web::Result<Json<metrics::AgentMetrics>> {
+    let agent_metrics = section(latest(&collector).await.metrics.agent)?;
+    Ok(Json(agent_metrics))
 }
@@ -0,0 +1,337 @@
+use agent_config::types::{WebhooksFormat, WebhooksHookConfig};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// Longest message Discord accepts, in characters
+const DISCORD_MAX_LENGTH: usize = 2000;
+/// Headers set by the agent, which custom headers can not replace
+const RESERVED_HEADER_PREFIXES: [&str; 2] = ["x-agent-", "x-hub-signature"];
+
+/// Request body for a hook, shaped by its format
+pub(crate) struct Payload {
+    pub body: String,
+    pub content_type: &'static str,
+    /// Headers the format needs (e.g. ntfy's title)
+    pub headers: Vec<(&'static str, String)>,
+}
+
+impl Payload {
+    fn json(body: String) -> Self {
+        Self {
+            body,
+            content_type: "application/json",
+            headers: vec![],
+        }
+    }
+    /// Content type a template renders, JSON unless a custom header replaces it
+    fn template_content_type(custom: &HashMap<String, String>) -> &str {
+        custom
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(CONTENT_TYPE.as_str()))
+            .map_or("application/json", |(_, value)| value.as_str())
+    }
+    /// Headers to send, custom headers replace those of the format but not the agent's own
+    pub fn header_map(&self, custom: &HashMap<String, String>) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static(self.content_type));
+        let format_headers = self
+            .headers
+            .iter()
+            .map(|(name, value)| (*name, value.as_str()));
+        let custom_headers = custom
+            .iter()
+            .filter(|(name, _)| {
+                let lower = name.to_ascii_lowercase();
+                let reserved = RESERVED_HEADER_PREFIXES
+                    .iter()
+                    .any(|prefix| lower.starts_with(prefix));
+                if reserved {
+                    log::warn!("skipping webhook header '{name}' as it is set by the agent");
+                }
+                !reserved
+            })
+            .map(|(name, value)| (name.as_str(), value.as_str()));
+        for (name, value) in format_headers.chain(custom_headers) {
+            match (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(value),
+            ) {
+                (Ok(name), Ok(value)) => {
+                    headers.insert(name, value);
+                }
+                _ => log::error!("skipping invalid webhook header '{name}'"),
+            }
+        }
+        headers
+    }
+}
+
+/// Short title & message describing a webhook, for chat & notification tools
+fn describe(body: &Value) -> (String, String) {
+    let agent_id = body["agent_id"].as_str().unwrap_or_default();
+    match body["hook_type"].as_str().unwrap_or_default() {
+        "ON_START" => (
+            format!("{agent_id} started"),
+            format!("Agent '{agent_id}' has started"),
+        ),
+        "ON_SHUTDOWN" => (
+            format!("{agent_id} stopping"),
+            format!("Agent '{agent_id}' is shutting down"),
+        ),
+        "PING" => (
+            format!("{agent_id} ping"),
+            format!("Agent '{agent_id}' is running"),
+        ),
+        "METRICS" => {
+            let metrics = &body["metrics"];
+            let mut parts = vec![];
+            if let Some(v) = metrics["cpu"]["load"]["average"].as_f64() {
+                parts.push(format!("CPU {v:.1}%"));
+            }
+            if let Some(v) = metrics["memory"]["perc_used"].as_f64() {
+                parts.push(format!("memory {v:.1}%"));
+            }
+            if let Some(v) = metrics["system"]["load_average"]["one"].as_f64() {
+                parts.push(format!("load {v:.2}"));
+            }
+            let message = match parts.is_empty() {
+                true => format!("Agent '{agent_id}' captured metrics"),
+                false => parts.join(", "),
+            };
+            (format!("{agent_id} metrics"), message)
+        }
+        "WATCHED_CHANGE" => {
+            let name = body["process"]["name"].as_str().unwrap_or_default();
+            let state = match body["process"]["running"].as_bool() {
+                Some(true) => "started",
+                _ => "stopped",
+            };
+            (
+                format!("{name} {state} on {agent_id}"),
+                format!("Watched process '{name}' {state} on agent '{agent_id}'"),
+            )
+        }
+        "ALERT_FIRING" => {
+            let alert = &body["alert"];
+            let severity = alert["severity"].as_str().unwrap_or_default();
+            (
+                format!(
+                    "[{}] {} firing on {agent_id}",
+                    severity.to_uppercase(),
+                    alert["rule"].as_str().unwrap_or_default()
+                ),
+                format!(
+                    "{} is {} ({} {})",
+                    alert["metric"].as_str().unwrap_or_default(),
+                    alert["value"],
+                    alert["comparator"].as_str().unwrap_or_default(),
+                    alert["threshold"]
+                ),
+            )
+        }
+        "ALERT_RESOLVED" => {
+            let alert = &body["alert"];
+            (
+                format!(
+                    "{} resolved on {agent_id}",
+                    alert["rule"].as_str().unwrap_or_default()
+                ),
+                format!(
+                    "{} is {}",
+                    alert["metric"].as_str().unwrap_or_default(),
+                    alert["value"]
+                ),
+            )
+        }
+        other => (format!("{agent_id} {other}"), String::new()),
+    }
+}
+
+/// ntfy priority & tags, so firing alerts stand out
+fn ntfy_priority(body: &Value) -> (&'static str, &'static str) {
+    match body["hook_type"].as_str().unwrap_or_default() {
+        "ALERT_FIRING" => match body["alert"]["severity"].as_str() {
+            Some("critical") => ("urgent", "rotating_light"),
+            Some("warning") => ("high", "warning"),
+            _ => ("default", "information_source"),
+        },
+        "ALERT_RESOLVED" => ("default", "white_check_mark"),
+        _ => ("default", "computer"),
+    }
+}
+
+/// Find the value at a dot separated path, list items are selected by index
+fn lookup<'a>(body: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .filter(|key| !key.is_empty())
+        .try_fold(body, |value, key| match value {
+            Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+            _ => value.get(key),
+        })
+}
+
+/// Render a template, replacing each `{{ path }}` with the value at that path in the body (e.g. `{{ metrics.memory.perc_used }}`).
+/// Strings are inserted as they are, unless followed by `| json` which inserts the JSON encoded value,
+/// missing values are left empty (or null when JSON encoded).
+/// When rendering JSON, strings are escaped so they can be placed within quotes
+fn render_template(template: &str, body: &Value, escape: bool) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = match after.find("}}") {
+            Some(v) => v,
+            None => {
+                // unclosed placeholders are left as they are
+                rest = &rest[start..];
+                break;
+            }
+        };
+        let expression = after[..end].trim();
+        let (path, as_json) = match expression
+            .strip_suffix("json")
+            .and_then(|v| v.trim_end().strip_suffix('|'))
+        {
+            Some(path) => (path.trim(), true),
+            None => (expression, false),
+        };
+        match (lookup(body, path), as_json) {
+            (Some(value @ Value::String(v)), false) => match escape {
+                true => {
+                    let quoted = value.to_string();
+                    rendered.push_str(&quoted[1..quoted.len() - 1]);
+                }
+                false => rendered.push_str(v),
+            },
+            (Some(v), _) => rendered.push_str(&v.to_string()),
+            (None, true) => rendered.push_str("null"),
+            (None, false) => {}
+        }
+        rest = &after[end + 2..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+/// Shape a webhook's JSON body into the hook's format
+pub(crate) fn render(
+    raw_body: &str,
+    client: &WebhooksHookConfig,
+    template: Option<&str>,
+) -> Payload {
+    if client.format == WebhooksFormat::Json {
+        return Payload::json(raw_body.to_string());
+    }
+    let body: Value = match serde_json::from_str(raw_body) {
+        Ok(v) => v,
+        Err(_) => return Payload::json(raw_body.to_string()),
+    };
+    let (title, message) = describe(&body);
+    match client.format {
+        WebhooksFormat::Json => Payload::json(raw_body.to_string()),
+        WebhooksFormat::Slack => Payload::json(
+            json!({
+                "text": format!("*{title}*\n{message}"),
+            })
+            .to_string(),
+        ),
+        WebhooksFormat::Discord => {
+            let content: String = format!("**{title}**\n{message}")
+                .chars()
+                .take(DISCORD_MAX_LENGTH)
+                .collect();
+            Payload::json(json!({ "content": content }).to_string())
+        }
+        WebhooksFormat::Teams => Payload::json(
+            json!({
+                "type": "message",
+                "attachments": [{
+                    "contentType": "application/vnd.microsoft.card.adaptive",
+                    "content": {
+                        "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+                        "type": "AdaptiveCard",
+                        "version": "1.2",
+                        "body": [
+                            {"type": "TextBlock", "text": title, "weight": "bolder", "size": "medium", "wrap": true},
+                            {"type": "TextBlock", "text": message, "wrap": true},
+                        ],
+                    },
+                }],
+            })
+            .to_string(),
+        ),
+        WebhooksFormat::Ntfy => {
+            let (priority, tags) = ntfy_priority(&body);
+            Payload {
+                body: match message.is_empty() {
+                    true => title.clone(),
+                    false => message,
+                },
+                content_type: "text/plain",
+                headers: vec![
+                    ("Title", title),
+                    ("Priority", priority.to_string()),
+                    ("Tags", tags.to_string()),
+                ],
+            }
+        }
+        WebhooksFormat::Template => match template {
+            Some(template) => {
+                // a custom Content-Type header is sent in place of the payload's
+                let content_type = Payload::template_content_type(&client.headers);
+                let escape = content_type.to_ascii_lowercase().contains("json");
+                Payload::json(render_template(template, &body, escape))
+            }
+            // already logged when the template failed to load
+            None => Payload::json(raw_body.to_string()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body() -> Value {
+        json!({
+            "agent_id": "web \"1\"",
+            "metrics": {"disks": [{"name": "sda", "perc_used": 12.5}]},
+        })
+    }
+
+    #[test]
+    fn json_template_strings_are_escaped() {
+        let rendered =
+            render_template(r#"{"text": "{{ agent_id }} {{ missing }}"}"#, &body(), true);
+        let parsed: Value = serde_json::from_str(&rendered).unwrap();
+        assert_eq!(parsed["text"], "web \"1\" ");
+    }
+
+    #[test]
+    fn text_template_strings_are_inserted_as_they_are() {
+        let rendered =
+            render_template("{{ agent_id }}: {{ metrics.disks.0.name }}", &body(), false);
+        assert_eq!(rendered, "web \"1\": sda");
+    }
+
+    #[test]
+    fn json_filter_encodes_values() {
+        let template = "{{ agent_id | json }} {{ metrics.disks.0 | json }} {{ missing | json }}";
+        for escape in [true, false] {
+            assert_eq!(
+                render_template(template, &body(), escape),
+                r#""web \"1\"" {"name":"sda","perc_used":12.5} null"#
+            );
+        }
+    }
+
+    #[test]
+    fn template_content_type_comes_from_custom_header() {
+        let mut headers = HashMap::new();
+        assert_eq!(Payload::template_content_type(&headers), "application/json");
+        headers.insert("content-type".to_string(), "text/plain".to_string());
+        assert_eq!(Payload::template_content_type(&headers), "text/plain");
+    }
+}
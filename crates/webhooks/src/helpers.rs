@@ -1,20 +1,25 @@
+use agent_config::types::WebhooksRetryConfig;
+use openssl::error::ErrorStack;
 use openssl::hash::MessageDigest;
-use openssl::pkey::PKey;
+use openssl::pkey::{Id, PKey, Private};
+use openssl::rand::rand_bytes;
 use openssl::sign::Signer;
 use reqwest::{
     header::{HeaderMap, HeaderValue},
     redirect::Policy,
     Client,
 };
-use std::time::Duration;
+use std::fs::read;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 static USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"),);
 
 /// Sign a webhooks body with HMAC-sha256
-pub fn sign_body(body: &String, secret: String) -> String {
+pub fn sign_body(body: &[u8], secret: &str) -> String {
     let key = PKey::hmac(secret.as_bytes()).unwrap();
     let mut signer = Signer::new(MessageDigest::sha256(), &key).unwrap();
-    signer.update(body.as_bytes()).unwrap();
+    signer.update(body).unwrap();
     let signed = signer.sign_to_vec().unwrap();
     signed
         .iter()
@@ -23,6 +28,61 @@ pub fn sign_body(body: &String, secret: String) -> String {
         .join("")
 }
 
+/// Load a PEM encoded Ed25519 private key
+pub fn load_signing_key(path: &Path) -> Result<PKey<Private>, String> {
+    let pem = read(path).map_err(|err| err.to_string())?;
+    let key = PKey::private_key_from_pem(&pem).map_err(|err| err.to_string())?;
+    if key.id() != Id::ED25519 {
+        return Err("key is not an Ed25519 key".to_string());
+    }
+    Ok(key)
+}
+
+/// Sign with an Ed25519 private key, giving the hex encoded signature
+pub fn sign_ed25519(key: &PKey<Private>, data: &[u8]) -> Result<String, ErrorStack> {
+    let signed = Signer::new_without_digest(key)?.sign_oneshot_to_vec(data)?;
+    Ok(signed.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Random id for a webhook, formatted as a version 4 UUID
+pub fn new_delivery_id() -> String {
+    let mut bytes = [0u8; 16];
+    rand_bytes(&mut bytes).expect("unable to generate delivery id");
+    // set the version & variant bits
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// Unix time in seconds
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|v| v.as_secs())
+        .unwrap_or_default()
+}
+
+/// Delay before retrying a delivery, growing after each attempt
+pub fn retry_delay(retry: &WebhooksRetryConfig, attempt: u32) -> Duration {
+    let delay = retry.initial_delay as f64 * retry.multiplier.powi(attempt as i32 - 1);
+    // random value between -1 & 1, so retries from many agents are spread out
+    let mut bytes = [0u8; 4];
+    let random = match rand_bytes(&mut bytes) {
+        Ok(_) => u32::from_le_bytes(bytes) as f64 / u32::MAX as f64 * 2.0 - 1.0,
+        Err(_) => 0.0,
+    };
+    let delay = delay * (1.0 + retry.jitter * random);
+    Duration::from_millis(delay.max(0.0) as u64)
+}
+
 /// Create a client ready for sending webhook requests
 pub fn new_client(timeout: Duration) -> Client {
     let mut headers = HeaderMap::new();
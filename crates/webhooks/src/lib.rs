@@ -1,65 +1,364 @@
 use agent_collector::CollectorState;
-use agent_config::types::{Config, WebhooksHookConfig};
-use agent_core::webhooks::{BaseBody, HookTypes, MetricsBody};
+use agent_config::types::{Config, WebhooksFormat, WebhooksHookConfig};
+use agent_core::alerts::AlertEvent;
+use agent_core::webhooks::{AlertBody, BaseBody, HookTypes, MetricsBody, WatchedChangeBody};
 use futures::{future::join_all, join};
+use openssl::pkey::{PKey, Private};
 use reqwest::Client;
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::future::Future;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
-use tokio::time::interval;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::watch;
+use tokio::time::{interval, sleep, timeout_at, Instant, Interval};
 
+mod format;
 mod helpers;
+mod outbox;
+pub mod verify;
 
-use helpers::{new_client, sign_body};
+use helpers::{
+    load_signing_key, new_client, new_delivery_id, retry_delay, sign_body, sign_ed25519, unix_now,
+};
+use outbox::{Outbox, OutboxCollector, OutboxEntry};
+use verify::{DELIVERY_HEADER, ED25519_SIGNATURE_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+
+/// Header holding which delivery attempt a request is, starting at 1
+const ATTEMPT_HEADER: &str = "X-Agent-Attempt";
 
 struct WebhookManager {
     client: Client,
     config: Config,
     collector: Arc<CollectorState>,
+    /// Queue of undelivered webhooks, when enabled
+    outbox: Option<Arc<Outbox>>,
+    /// Ed25519 private keys used by hooks, by path
+    signing_keys: HashMap<PathBuf, PKey<Private>>,
+    /// Templates used by hooks, by path
+    templates: HashMap<PathBuf, String>,
+    /// Set once the agent is shutting down, so webhooks being sent are queued instead
+    stopping: watch::Sender<bool>,
 }
 
-impl<'a> WebhookManager {
+impl WebhookManager {
     fn new(config: Config, collector: Arc<CollectorState>) -> Self {
+        let outbox = config.webhooks.outbox.path.as_ref().and_then(|path| {
+            match Outbox::open(path.clone(), config.webhooks.outbox.clone()) {
+                Ok(v) => Some(Arc::new(v)),
+                Err(err) => {
+                    log::error!(
+                        "unable to open webhook outbox at '{}' due to '{}', outbox disabled",
+                        path.display(),
+                        err
+                    );
+                    None
+                }
+            }
+        });
+        if let Some(outbox) = &outbox {
+            collector.register(Box::new(OutboxCollector(outbox.clone())));
+        }
+        // hooks with a key that can not be loaded are sent unsigned, so receivers reject them
+        let signing_keys = config
+            .webhooks
+            .signing_keys()
+            .into_iter()
+            .filter_map(|path| match load_signing_key(&path) {
+                Ok(key) => Some((path, key)),
+                Err(err) => {
+                    log::error!(
+                        "unable to load signing key '{}' due to '{}'",
+                        path.display(),
+                        err
+                    );
+                    None
+                }
+            })
+            .collect();
+        // hooks with a template that can not be loaded are sent the JSON body
+        let mut templates = HashMap::new();
+        for hook in config.webhooks.hooks() {
+            if hook.format != WebhooksFormat::Template {
+                continue;
+            }
+            let path = match hook.template {
+                Some(v) => v,
+                None => {
+                    log::error!(
+                        "hook '{}' has the template format but no template",
+                        hook.url
+                    );
+                    continue;
+                }
+            };
+            if templates.contains_key(&path) {
+                continue;
+            }
+            match read_to_string(&path) {
+                Ok(template) => {
+                    templates.insert(path, template);
+                }
+                Err(err) => log::error!(
+                    "unable to load template '{}' due to '{}'",
+                    path.display(),
+                    err
+                ),
+            }
+        }
         Self {
             client: new_client(Duration::from_secs(config.timeout)),
             config,
             collector,
+            outbox,
+            signing_keys,
+            templates,
+            stopping: watch::channel(false).0,
+        }
+    }
+    /// Wait for the next tick, returning false once the agent is shutting down
+    async fn tick(&self, interval: &mut Interval) -> bool {
+        tokio::select! {
+            _ = interval.tick() => true,
+            _ = self.stopped() => false,
+        }
+    }
+    /// Wait until the agent is shutting down
+    async fn stopped(&self) {
+        let mut stopping = self.stopping.subscribe();
+        while !*stopping.borrow_and_update() {
+            if stopping.changed().await.is_err() {
+                break;
+            }
         }
     }
-    /// Send webook to client, signing the body if required
+    /// Send webook to client in its format, signing the body if required.
+    /// Failed deliveries are retried following the hook's retry policy,
+    /// returning whether the webhook was delivered
     async fn send_to_client(
         &self,
-        raw_body: &String,
+        raw_body: &str,
         client: &WebhooksHookConfig,
         hook_type: &HookTypes,
-    ) {
-        let mut response = self.client.post(client.url.clone()).body(raw_body.clone());
-        if client.secret.is_some() {
-            // add signature header as hook has a secret
-            response = response.header(
-                "X-Hub-Signature-256",
-                sign_body(raw_body, client.secret.clone().unwrap()),
-            );
-        }
-        let response = response.send().await;
-        match response {
-            Err(err) => log::error!("failed to send webhook '{:?}' due to '{}'", hook_type, err),
-            Ok(resp) => {
-                if resp.status().is_success() {
-                    log::info!(
-                        "success sending webhook '{:?}' to '{}'",
+        delivery: &str,
+    ) -> bool {
+        let template = client
+            .template
+            .as_ref()
+            .and_then(|path| self.templates.get(path))
+            .map(String::as_str);
+        let payload = format::render(raw_body, client, template);
+        let headers = payload.header_map(&client.headers);
+        let max_attempts = client.retry.max_attempts.max(1);
+        for attempt in 1..=max_attempts {
+            // timestamped on each attempt, so retries are not rejected as too old
+            let timestamp = unix_now();
+            let mut response = self
+                .client
+                .post(client.url.clone())
+                .headers(headers.clone())
+                .header(ATTEMPT_HEADER, attempt)
+                .header(TIMESTAMP_HEADER, timestamp)
+                .header(DELIVERY_HEADER, delivery)
+                .body(payload.body.clone());
+            if let Some(secret) = &client.secret {
+                // add signature headers as hook has a secret,
+                // X-Hub-Signature-256 only covers the body so is kept for existing receivers
+                response = response
+                    .header(
+                        "X-Hub-Signature-256",
+                        sign_body(payload.body.as_bytes(), secret),
+                    )
+                    .header(
+                        SIGNATURE_HEADER,
+                        verify::signature(secret, timestamp, delivery, payload.body.as_bytes()),
+                    );
+            }
+            if let Some(key) = client
+                .signing_key
+                .as_ref()
+                .and_then(|path| self.signing_keys.get(path))
+            {
+                let signed = verify::signed_payload(timestamp, delivery, payload.body.as_bytes());
+                match sign_ed25519(key, &signed) {
+                    Ok(signature) => {
+                        response = response.header(ED25519_SIGNATURE_HEADER, signature);
+                    }
+                    Err(err) => log::error!("unable to sign webhook due to '{err}'"),
+                }
+            }
+            let response = response.send().await;
+            let retryable = match response {
+                Err(err) => {
+                    log::error!(
+                        "failed to send webhook '{:?}' to '{}' due to '{}'",
                         hook_type,
                         client.url,
+                        err
                     );
-                } else {
+                    true
+                }
+                Ok(resp) => {
+                    if resp.status().is_success() {
+                        log::info!(
+                            "success sending webhook '{:?}' to '{}'",
+                            hook_type,
+                            client.url,
+                        );
+                        return true;
+                    }
                     log::error!(
                         "failed to send webhook '{:?}' to '{}' status code was '{}'",
                         hook_type,
                         client.url,
                         resp.status()
                     );
+                    client
+                        .retry
+                        .retry_statuses
+                        .contains(&resp.status().as_u16())
                 }
+            };
+            if !retryable || attempt == max_attempts {
+                break;
             }
+            let delay = retry_delay(&client.retry, attempt);
+            log::info!(
+                "retrying webhook '{:?}' to '{}' in '{:?}'",
+                hook_type,
+                client.url,
+                delay
+            );
+            sleep(delay).await;
+        }
+        false
+    }
+    /// Send webhook to client, queueing it in the outbox if it could not be delivered.
+    /// Sending stops once the agent is shutting down, queueing the webhook so it is sent
+    /// after a restart.
+    async fn deliver(
+        &self,
+        raw_body: &str,
+        hook: usize,
+        client: &WebhooksHookConfig,
+        hook_type: &HookTypes,
+    ) {
+        let delivery = new_delivery_id();
+        // queued behind earlier webhooks to the same url so they arrive in order
+        let waiting = self
+            .outbox
+            .as_ref()
+            .is_some_and(|outbox| outbox.has_waiting(&client.url));
+        let sent = !waiting
+            && tokio::select! {
+                sent = self.send_to_client(raw_body, client, hook_type, &delivery) => sent,
+                _ = self.stopped() => {
+                    log::info!(
+                        "stopped sending webhook '{:?}' to '{}' as shutting down",
+                        hook_type,
+                        client.url
+                    );
+                    false
+                }
+            };
+        if !sent {
+            self.queue(raw_body, hook, client, hook_type, &delivery);
+        }
+    }
+    /// Queue an undelivered webhook in the outbox, when enabled.
+    /// The hook is its position in the config's list for the hook type
+    fn queue(
+        &self,
+        raw_body: &str,
+        hook: usize,
+        client: &WebhooksHookConfig,
+        hook_type: &HookTypes,
+        delivery: &str,
+    ) {
+        if let Some(outbox) = &self.outbox {
+            outbox.push(&client.url, hook, delivery, hook_type.clone(), raw_body);
+        }
+    }
+    /// Hooks sending the hook type, in config order
+    fn hooks_for(&self, hook_type: &HookTypes) -> Vec<WebhooksHookConfig> {
+        let webhooks = &self.config.webhooks;
+        match hook_type {
+            HookTypes::OnStart => webhooks.on_start.clone(),
+            HookTypes::OnShutdown => webhooks.on_shutdown.clone(),
+            HookTypes::AlertFiring | HookTypes::AlertResolved => webhooks.on_alert.clone(),
+            HookTypes::Ping => webhooks
+                .interval_pings
+                .iter()
+                .map(|v| v.into_base())
+                .collect(),
+            HookTypes::Metrics => webhooks
+                .interval_metrics
+                .iter()
+                .map(|v| v.into_base())
+                .collect(),
+            HookTypes::WatchedChange => webhooks
+                .on_watched_change
+                .iter()
+                .map(|v| v.into_base())
+                .collect(),
+        }
+    }
+    /// Find the hook a queued webhook is for, as secrets are not stored in the outbox.
+    /// Falls back to a hook of the same type & url if the config changed since it was queued
+    fn find_hook(&self, entry: &OutboxEntry) -> Option<WebhooksHookConfig> {
+        let hooks = self.hooks_for(&entry.hook_type);
+        let queued = entry
+            .hook
+            .and_then(|hook| hooks.get(hook))
+            .filter(|hook| hook.url == entry.url)
+            .cloned();
+        queued.or_else(|| hooks.into_iter().find(|hook| hook.url == entry.url))
+    }
+    /// Deliver queued webhooks in order, stopping at the first failure for each url
+    async fn replay_outbox(&self) {
+        let outbox = match &self.outbox {
+            Some(v) => v,
+            None => return,
         };
+        let mut interval = interval(Duration::from_secs(
+            self.config.webhooks.outbox.retry_interval.max(1),
+        ));
+        while self.tick(&mut interval).await {
+            outbox.remove_expired();
+            for url in outbox.urls() {
+                while let Some((seq, entry)) = outbox.next_for(&url) {
+                    let client = match self.find_hook(&entry) {
+                        Some(v) => v,
+                        None => {
+                            log::warn!(
+                                "dropping queued webhook '{:?}' to '{}' as it is no longer a hook",
+                                entry.hook_type,
+                                url
+                            );
+                            outbox.remove(seq);
+                            continue;
+                        }
+                    };
+                    // left in the outbox when shutting down
+                    let sending = self.send_to_client(
+                        &entry.body,
+                        &client,
+                        &entry.hook_type,
+                        &entry.delivery,
+                    );
+                    let sent = tokio::select! {
+                        sent = sending => sent,
+                        _ = self.stopped() => return,
+                    };
+                    if !sent {
+                        break;
+                    }
+                    outbox.remove(seq);
+                }
+            }
+        }
     }
     /// Sends webhook to all clients concurrently
     async fn send_to_clients(&self, body: BaseBody, clients: &[WebhooksHookConfig]) {
@@ -67,7 +366,8 @@ impl<'a> WebhookManager {
         // TODO switch to std::futures when it's out of experimental
         let to_send = clients
             .iter()
-            .map(|hook| self.send_to_client(&raw_body, hook, &body.hook_type));
+            .enumerate()
+            .map(|(i, hook)| self.deliver(&raw_body, i, hook, &body.hook_type));
         join_all(to_send).await;
     }
     async fn send_on_start(&self) {
@@ -79,18 +379,52 @@ impl<'a> WebhookManager {
         self.send_to_clients(body, &self.config.webhooks.on_start)
             .await;
     }
+    /// Send on_shutdown webhooks straight away, even if earlier webhooks are waiting.
+    /// Those not delivered by the shutdown deadline are queued, to be sent after a restart
+    async fn send_on_shutdown(&self, deadline: Instant) {
+        if self.config.webhooks.on_shutdown.is_empty() {
+            return;
+        }
+        let body = BaseBody {
+            agent_id: self.config.id.clone(),
+            sent_at: SystemTime::now(),
+            hook_type: HookTypes::OnShutdown,
+        };
+        let raw_body = serde_json::to_string(&body).expect("unable to serialize webhook");
+        let to_send = self.config.webhooks.on_shutdown.iter().enumerate();
+        let to_send = to_send.map(|(i, hook)| {
+            let raw_body = &raw_body;
+            async move {
+                let delivery = new_delivery_id();
+                let sending =
+                    self.send_to_client(raw_body, hook, &HookTypes::OnShutdown, &delivery);
+                match timeout_at(deadline, sending).await {
+                    Ok(true) => {}
+                    Ok(false) => self.queue(raw_body, i, hook, &HookTypes::OnShutdown, &delivery),
+                    Err(_) => {
+                        log::warn!(
+                            "shutdown timeout passed before on_shutdown webhook to '{}' was sent",
+                            hook.url
+                        );
+                        self.queue(raw_body, i, hook, &HookTypes::OnShutdown, &delivery);
+                    }
+                }
+            }
+        });
+        join_all(to_send).await;
+    }
     async fn send_interval_metrics(&self) {
         let senders = self
             .config
             .webhooks
             .interval_metrics
             .iter()
-            .map(|client| async {
+            .enumerate()
+            .map(|(i, client)| async move {
                 let mut interval = interval(Duration::from_secs(client.interval));
                 let client_config = client.into_base();
-                loop {
-                    interval.tick().await;
-                    let metrics = self.collector.metrics();
+                while self.tick(&mut interval).await {
+                    let metrics = CollectorState::metrics_async(self.collector.clone()).await;
                     let body = MetricsBody {
                         agent_id: self.config.id.clone(),
                         sent_at: SystemTime::now(),
@@ -99,7 +433,7 @@ impl<'a> WebhookManager {
                     };
                     let raw_body =
                         serde_json::to_string(&body).expect("unable to serialize webhook");
-                    self.send_to_client(&raw_body, &client_config, &HookTypes::Metrics)
+                    self.deliver(&raw_body, i, &client_config, &HookTypes::Metrics)
                         .await;
                 }
             });
@@ -115,8 +449,7 @@ impl<'a> WebhookManager {
             .map(|client| async {
                 let mut interval = interval(Duration::from_secs(client.interval));
                 let client_config = client.into_base();
-                loop {
-                    interval.tick().await;
+                while self.tick(&mut interval).await {
                     let body = BaseBody {
                         agent_id: self.config.id.clone(),
                         sent_at: SystemTime::now(),
@@ -124,31 +457,128 @@ impl<'a> WebhookManager {
                     };
                     let raw_body =
                         serde_json::to_string(&body).expect("unable to serialize webhook");
-                    self.send_to_client(&raw_body, &client_config, &HookTypes::Ping)
-                        .await;
+                    let delivery = new_delivery_id();
+                    // pings are not queued, so are dropped when shutting down
+                    let sending =
+                        self.send_to_client(&raw_body, &client_config, &HookTypes::Ping, &delivery);
+                    tokio::select! {
+                        _ = sending => {}
+                        _ = self.stopped() => break,
+                    }
                 }
             });
         join_all(senders).await;
     }
 
-    // run all async tasks, best used with tokio::spawn to allow aborting.
+    async fn send_on_watched_change(&self) {
+        let senders = self
+            .config
+            .webhooks
+            .on_watched_change
+            .iter()
+            .enumerate()
+            .map(|(i, client)| async move {
+                let mut interval = interval(Duration::from_secs(client.interval));
+                let client_config = client.into_base();
+                // last known running state of each watched process
+                let mut last_running: HashMap<String, bool> = HashMap::new();
+                while self.tick(&mut interval).await {
+                    let metrics = CollectorState::metrics_async(self.collector.clone()).await;
+                    for process in metrics.metrics.watched.unwrap_or_default() {
+                        let previous = last_running.insert(process.name.clone(), process.running);
+                        // first check only records the state
+                        if previous.is_none() || previous == Some(process.running) {
+                            continue;
+                        }
+                        let body = WatchedChangeBody {
+                            agent_id: self.config.id.clone(),
+                            sent_at: SystemTime::now(),
+                            hook_type: HookTypes::WatchedChange,
+                            process,
+                        };
+                        let raw_body =
+                            serde_json::to_string(&body).expect("unable to serialize webhook");
+                        self.deliver(&raw_body, i, &client_config, &HookTypes::WatchedChange)
+                            .await;
+                    }
+                }
+            });
+        join_all(senders).await;
+    }
+
+    async fn send_on_alert(&self) {
+        if self.config.webhooks.on_alert.is_empty() {
+            return;
+        }
+        let mut events = self.collector.subscribe_alerts();
+        loop {
+            let event = tokio::select! {
+                event = events.recv() => event,
+                _ = self.stopped() => break,
+            };
+            let (hook_type, alert) = match event {
+                Ok(AlertEvent::Firing(alert)) => (HookTypes::AlertFiring, alert),
+                Ok(AlertEvent::Resolved(alert)) => (HookTypes::AlertResolved, alert),
+                Err(RecvError::Lagged(skipped)) => {
+                    log::warn!(
+                        "skipped sending '{skipped}' alert webhooks, as too many were queued"
+                    );
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            let body = AlertBody {
+                agent_id: self.config.id.clone(),
+                sent_at: SystemTime::now(),
+                hook_type,
+                alert,
+            };
+            let raw_body = serde_json::to_string(&body).expect("unable to serialize webhook");
+            let to_send = self
+                .config
+                .webhooks
+                .on_alert
+                .iter()
+                .enumerate()
+                .map(|(i, hook)| self.deliver(&raw_body, i, hook, &body.hook_type));
+            join_all(to_send).await;
+        }
+    }
+
+    // run all async tasks, until the agent is shutting down
     async fn run(&self) {
         join!(
             self.send_on_start(),
             self.send_interval_pings(),
-            self.send_interval_metrics()
+            self.send_interval_metrics(),
+            self.send_on_watched_change(),
+            self.send_on_alert(),
+            self.replay_outbox()
         );
     }
 }
 
-// Start the webhook server, waiting for CTRL+C
-pub async fn run(config: &Config, collector: Arc<CollectorState>) {
-    let webhook_manager = WebhookManager::new(config.clone(), collector.clone());
+// Start the webhook server, until shutdown completes then send on_shutdown webhooks
+pub async fn run(
+    config: &Config,
+    collector: Arc<CollectorState>,
+    shutdown: impl Future<Output = ()>,
+) {
+    let webhook_manager = Arc::new(WebhookManager::new(config.clone(), collector.clone()));
     log::info!("starting webhooks server");
-    let handle = tokio::spawn(async move { webhook_manager.run().await });
-    tokio::signal::ctrl_c()
-        .await
-        .expect("failed to listen for exit signal");
-    log::info!("SIGINT received; forcing shutdown of webhooks server");
-    handle.abort();
+    let mut handle = tokio::spawn({
+        let webhook_manager = webhook_manager.clone();
+        async move { webhook_manager.run().await }
+    });
+    shutdown.await;
+    log::info!("stopping webhooks server");
+    // one deadline covers stopping & sending on_shutdown, so shutdown never exceeds the timeout
+    let deadline = Instant::now() + Duration::from_secs(config.webhooks.shutdown_timeout);
+    // webhooks being sent are queued once stopping, so the tasks finish promptly
+    webhook_manager.stopping.send_replace(true);
+    if timeout_at(deadline, &mut handle).await.is_err() {
+        log::warn!("webhooks did not stop before the shutdown timeout");
+        handle.abort();
+    }
+    webhook_manager.send_on_shutdown(deadline).await;
 }
@@ -0,0 +1,217 @@
+use agent_collector::Collector;
+use agent_config::types::WebhooksOutboxConfig;
+use agent_core::metrics::{Metrics, OutboxMetrics};
+use agent_core::webhooks::HookTypes;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{create_dir_all, read_dir, read_to_string, remove_file, rename, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::helpers::unix_now;
+
+/// Webhook waiting to be delivered, secrets are not stored so the hook is looked up on replay
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct OutboxEntry {
+    /// Unix time in seconds the webhook was queued
+    pub queued_at: u64,
+    pub url: String,
+    /// Position of the hook in the config's list for the hook type,
+    /// absent for webhooks queued by older versions
+    #[serde(default)]
+    pub hook: Option<usize>,
+    /// Id sent with every attempt, so receivers can drop duplicates
+    pub delivery: String,
+    pub hook_type: HookTypes,
+    pub body: String,
+}
+
+/// Url & queue time of each entry, by sequence number
+type Index = BTreeMap<u64, (String, u64)>;
+
+/// Queue of undelivered webhooks, stored as one file per webhook so they survive restarts
+pub(crate) struct Outbox {
+    config: WebhooksOutboxConfig,
+    dir: PathBuf,
+    index: Mutex<Index>,
+}
+
+/// Write a file via a temporary file in the same directory, so a crash never leaves it half written
+fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let temp = path.with_extension("tmp");
+    let mut file = File::create(&temp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    rename(&temp, path)
+}
+
+impl Outbox {
+    /// Open the outbox, loading any webhooks queued before a restart
+    pub fn open(dir: PathBuf, config: WebhooksOutboxConfig) -> io::Result<Self> {
+        create_dir_all(&dir)?;
+        let mut index = Index::new();
+        for entry in read_dir(&dir)? {
+            let path = entry?.path();
+            // left by a write interrupted before being renamed into place
+            if path.extension().is_some_and(|ext| ext == "tmp") {
+                remove_file(&path).ok();
+                continue;
+            }
+            let seq = match path.file_stem().and_then(|v| v.to_str()) {
+                Some(v) if path.extension().is_some_and(|ext| ext == "json") => {
+                    match v.parse::<u64>() {
+                        Ok(v) => v,
+                        Err(_) => continue,
+                    }
+                }
+                _ => continue,
+            };
+            let loaded = read_to_string(&path)
+                .ok()
+                .and_then(|raw| serde_json::from_str::<OutboxEntry>(&raw).ok());
+            match loaded {
+                Some(entry) => {
+                    index.insert(seq, (entry.url, entry.queued_at));
+                }
+                None => {
+                    log::warn!("removing unreadable outbox entry '{}'", path.display());
+                    remove_file(&path).ok();
+                }
+            }
+        }
+        if !index.is_empty() {
+            log::info!("outbox has '{}' webhooks waiting", index.len());
+        }
+        Ok(Self {
+            config,
+            dir,
+            index: Mutex::new(index),
+        })
+    }
+    fn entry_path(&self, seq: u64) -> PathBuf {
+        // padded so entries sort in order on disk
+        self.dir.join(format!("{seq:020}.json"))
+    }
+    fn remove_entry(&self, index: &mut Index, seq: u64) {
+        index.remove(&seq);
+        if let Err(err) = remove_file(self.entry_path(seq)) {
+            log::error!("unable to remove outbox entry due to '{err}'");
+        }
+    }
+    /// Queue a webhook, dropping the oldest when full
+    pub fn push(&self, url: &str, hook: usize, delivery: &str, hook_type: HookTypes, body: &str) {
+        let mut index = self.index.lock().unwrap();
+        let seq = index.keys().next_back().map_or(0, |v| v + 1);
+        let entry = OutboxEntry {
+            queued_at: unix_now(),
+            url: url.to_string(),
+            hook: Some(hook),
+            delivery: delivery.to_string(),
+            hook_type,
+            body: body.to_string(),
+        };
+        let raw = serde_json::to_string(&entry).expect("unable to serialize outbox entry");
+        if let Err(err) = write_atomic(&self.entry_path(seq), raw.as_bytes()) {
+            log::error!("unable to queue webhook in outbox due to '{err}'");
+            return;
+        }
+        log::info!(
+            "queued webhook '{:?}' to '{}' in outbox",
+            entry.hook_type,
+            url
+        );
+        index.insert(seq, (entry.url, entry.queued_at));
+        while index.len() > self.config.max_entries {
+            let oldest = *index.keys().next().expect("outbox is not empty");
+            log::warn!("outbox full, dropping oldest webhook");
+            self.remove_entry(&mut index, oldest);
+        }
+    }
+    /// Whether webhooks to the url are waiting, new webhooks must queue behind them to keep order
+    pub fn has_waiting(&self, url: &str) -> bool {
+        self.index
+            .lock()
+            .unwrap()
+            .values()
+            .any(|(entry_url, _)| entry_url == url)
+    }
+    /// Urls with webhooks waiting
+    pub fn urls(&self) -> Vec<String> {
+        let mut urls: Vec<String> = self
+            .index
+            .lock()
+            .unwrap()
+            .values()
+            .map(|(url, _)| url.to_string())
+            .collect();
+        urls.sort();
+        urls.dedup();
+        urls
+    }
+    /// Oldest waiting webhook for the url
+    pub fn next_for(&self, url: &str) -> Option<(u64, OutboxEntry)> {
+        let seq = *self
+            .index
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(_, (entry_url, _))| entry_url == url)?
+            .0;
+        match read_to_string(self.entry_path(seq))
+            .ok()
+            .and_then(|raw| serde_json::from_str(&raw).ok())
+        {
+            Some(entry) => Some((seq, entry)),
+            None => {
+                log::warn!("removing unreadable outbox entry '{seq}'");
+                self.remove(seq);
+                None
+            }
+        }
+    }
+    /// Remove a webhook, once delivered
+    pub fn remove(&self, seq: u64) {
+        let mut index = self.index.lock().unwrap();
+        self.remove_entry(&mut index, seq);
+    }
+    /// Remove webhooks older than the max age
+    pub fn remove_expired(&self) {
+        let now = unix_now();
+        let mut index = self.index.lock().unwrap();
+        let expired: Vec<u64> = index
+            .iter()
+            .filter(|(_, (_, queued_at))| now.saturating_sub(*queued_at) > self.config.max_age)
+            .map(|(seq, _)| *seq)
+            .collect();
+        if !expired.is_empty() {
+            log::warn!("dropping '{}' expired webhooks from outbox", expired.len());
+        }
+        for seq in expired {
+            self.remove_entry(&mut index, seq);
+        }
+    }
+    pub fn metrics(&self) -> OutboxMetrics {
+        let index = self.index.lock().unwrap();
+        OutboxMetrics {
+            depth: index.len() as u64,
+            oldest_age: index
+                .values()
+                .map(|(_, queued_at)| *queued_at)
+                .min()
+                .map(|queued_at| unix_now().saturating_sub(queued_at)),
+        }
+    }
+}
+
+/// Reports the outbox as agent self-metrics
+pub(crate) struct OutboxCollector(pub Arc<Outbox>);
+
+impl Collector for OutboxCollector {
+    fn name(&self) -> &str {
+        "outbox"
+    }
+    fn collect(&self, metrics: &mut Metrics) {
+        metrics.agent.get_or_insert_with(Default::default).outbox = Some(self.0.metrics());
+    }
+}
@@ -0,0 +1,351 @@
+use openssl::memcmp;
+use openssl::pkey::PKey;
+use openssl::sign::Verifier;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::helpers::sign_body;
+
+/// Header holding the unix time in seconds the request was sent
+pub const TIMESTAMP_HEADER: &str = "X-Agent-Timestamp";
+/// Header holding the id of the webhook, the same for every attempt to deliver it
+pub const DELIVERY_HEADER: &str = "X-Agent-Delivery";
+/// Header holding the HMAC-sha256 of the timestamp, delivery id & body
+pub const SIGNATURE_HEADER: &str = "X-Agent-Signature-256";
+/// Header holding the Ed25519 signature of the timestamp, delivery id & body
+pub const ED25519_SIGNATURE_HEADER: &str = "X-Agent-Signature-Ed25519";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum VerifyError {
+    /// Timestamp header is not a unix time in seconds
+    InvalidTimestamp,
+    /// Delivery id is not a UUID as sent by the agent
+    InvalidDelivery,
+    /// Timestamp is further from now than the tolerance
+    Expired,
+    /// Signature does not match, the request was not sent with the secret or was changed
+    InvalidSignature,
+    /// Public key is not a PEM encoded Ed25519 key
+    InvalidKey,
+}
+
+/// Data covered by signatures, the timestamp, delivery id & body are joined by '.'
+/// so a captured request can not be resent with a newer timestamp
+pub fn signed_payload(timestamp: u64, delivery: &str, body: &[u8]) -> Vec<u8> {
+    let mut signed = format!("{timestamp}.{delivery}.").into_bytes();
+    signed.extend_from_slice(body);
+    signed
+}
+
+/// Sign a webhook, giving the value of the signature header
+pub fn signature(secret: &str, timestamp: u64, delivery: &str, body: &[u8]) -> String {
+    sign_body(&signed_payload(timestamp, delivery, body), secret)
+}
+
+/// Parse the timestamp header, checking it is within the tolerance of now
+fn check_timestamp(timestamp: &str, tolerance: Duration) -> Result<u64, VerifyError> {
+    let timestamp: u64 = timestamp
+        .trim()
+        .parse()
+        .map_err(|_| VerifyError::InvalidTimestamp)?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|v| v.as_secs())
+        .unwrap_or_default();
+    if now.abs_diff(timestamp) > tolerance.as_secs() {
+        return Err(VerifyError::Expired);
+    }
+    Ok(timestamp)
+}
+
+/// Whether the delivery id is a UUID, as generated by the agent.
+/// Other ids are rejected, as a '.' in the id would let bytes move between it & the body
+/// without changing the signed payload
+fn is_delivery_id(delivery: &str) -> bool {
+    delivery.len() == 36
+        && delivery.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        })
+}
+
+/// Check the timestamp & delivery id headers, giving the timestamp
+fn check_headers(timestamp: &str, delivery: &str, tolerance: Duration) -> Result<u64, VerifyError> {
+    let timestamp = check_timestamp(timestamp, tolerance)?;
+    if !is_delivery_id(delivery) {
+        return Err(VerifyError::InvalidDelivery);
+    }
+    Ok(timestamp)
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    let value = value.trim();
+    if !value.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Verify a received webhook from its headers & raw body,
+/// the timestamp must be within the tolerance of now (300 seconds is suggested).
+/// Receivers should also drop delivery ids already seen within the tolerance, to stop replays
+pub fn verify(
+    secret: &str,
+    timestamp: &str,
+    delivery: &str,
+    signature_header: &str,
+    body: &[u8],
+    tolerance: Duration,
+) -> Result<(), VerifyError> {
+    let timestamp = check_headers(timestamp, delivery, tolerance)?;
+    let expected = signature(secret, timestamp, delivery, body);
+    let given = signature_header.trim().to_ascii_lowercase();
+    // compared in constant time, so the signature can not be guessed byte by byte
+    if expected.len() != given.len() || !memcmp::eq(expected.as_bytes(), given.as_bytes()) {
+        return Err(VerifyError::InvalidSignature);
+    }
+    Ok(())
+}
+
+/// Verify a received webhook signed with an Ed25519 key, using the agent's PEM encoded public key.
+/// As with [verify], receivers should also drop delivery ids already seen within the tolerance
+pub fn verify_ed25519(
+    public_key: &[u8],
+    timestamp: &str,
+    delivery: &str,
+    signature_header: &str,
+    body: &[u8],
+    tolerance: Duration,
+) -> Result<(), VerifyError> {
+    let key = PKey::public_key_from_pem(public_key).map_err(|_| VerifyError::InvalidKey)?;
+    let timestamp = check_headers(timestamp, delivery, tolerance)?;
+    let signature = decode_hex(signature_header).ok_or(VerifyError::InvalidSignature)?;
+    let valid = Verifier::new_without_digest(&key)
+        .and_then(|mut verifier| {
+            verifier.verify_oneshot(&signature, &signed_payload(timestamp, delivery, body))
+        })
+        .map_err(|_| VerifyError::InvalidKey)?;
+    match valid {
+        true => Ok(()),
+        false => Err(VerifyError::InvalidSignature),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::sign_ed25519;
+    use openssl::pkey::Private;
+
+    const DELIVERY: &str = "2b4a1f0e-3c7d-4e8f-9a6b-1d2c3e4f5a6b";
+    const BODY: &[u8] = br#"{"hook_type":"ON_START"}"#;
+    const TOLERANCE: Duration = Duration::from_secs(300);
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    /// Ways a webhook is signed, each with its own key
+    #[derive(Debug)]
+    enum Scheme {
+        Hmac(String),
+        Ed25519(PKey<Private>),
+    }
+
+    impl Scheme {
+        fn hmac(secret: &str) -> Self {
+            Self::Hmac(secret.to_string())
+        }
+        fn ed25519() -> Self {
+            Self::Ed25519(PKey::generate_ed25519().unwrap())
+        }
+        /// Every scheme, with new keys
+        fn all() -> Vec<Self> {
+            vec![Self::hmac("my_secret"), Self::ed25519()]
+        }
+        fn sign(&self, timestamp: u64, delivery: &str, body: &[u8]) -> String {
+            match self {
+                Self::Hmac(secret) => signature(secret, timestamp, delivery, body),
+                Self::Ed25519(key) => {
+                    sign_ed25519(key, &signed_payload(timestamp, delivery, body)).unwrap()
+                }
+            }
+        }
+        fn verify(
+            &self,
+            timestamp: &str,
+            delivery: &str,
+            signature_header: &str,
+            body: &[u8],
+        ) -> Result<(), VerifyError> {
+            match self {
+                Self::Hmac(secret) => verify(
+                    secret,
+                    timestamp,
+                    delivery,
+                    signature_header,
+                    body,
+                    TOLERANCE,
+                ),
+                Self::Ed25519(key) => verify_ed25519(
+                    &key.public_key_to_pem().unwrap(),
+                    timestamp,
+                    delivery,
+                    signature_header,
+                    body,
+                    TOLERANCE,
+                ),
+            }
+        }
+    }
+
+    /// A webhook signed now less the age with [DELIVERY] & [BODY], received as given
+    struct Case {
+        name: &'static str,
+        /// Seconds before now the webhook was signed
+        age: i64,
+        /// Timestamp header received, the signed timestamp when not given
+        timestamp: Option<&'static str>,
+        delivery: &'static str,
+        body: &'static [u8],
+        expected: Result<(), VerifyError>,
+    }
+
+    fn cases() -> Vec<Case> {
+        let case = |name, expected| Case {
+            name,
+            age: 0,
+            timestamp: None,
+            delivery: DELIVERY,
+            body: BODY,
+            expected,
+        };
+        vec![
+            case("unchanged", Ok(())),
+            Case {
+                age: 60,
+                ..case("within tolerance", Ok(()))
+            },
+            Case {
+                body: br#"{"hook_type":"ON_SHUTDOWN"}"#,
+                ..case("tampered body", Err(VerifyError::InvalidSignature))
+            },
+            Case {
+                delivery: "9f8e7d6c-5b4a-4392-8170-6f5e4d3c2b1a",
+                ..case("tampered delivery", Err(VerifyError::InvalidSignature))
+            },
+            Case {
+                delivery: "not-a-delivery-id",
+                ..case("malformed delivery", Err(VerifyError::InvalidDelivery))
+            },
+            Case {
+                delivery: "2B4A1F0E-3C7D-4E8F-9A6B-1D2C3E4F5A6B",
+                ..case("upper case delivery", Err(VerifyError::InvalidSignature))
+            },
+            Case {
+                age: 600,
+                ..case("stale timestamp", Err(VerifyError::Expired))
+            },
+            Case {
+                age: -600,
+                ..case("future timestamp", Err(VerifyError::Expired))
+            },
+            Case {
+                timestamp: Some("yesterday"),
+                ..case("non numeric timestamp", Err(VerifyError::InvalidTimestamp))
+            },
+            Case {
+                timestamp: Some(""),
+                ..case("missing timestamp", Err(VerifyError::InvalidTimestamp))
+            },
+        ]
+    }
+
+    #[test]
+    fn received_webhooks_are_verified() {
+        for scheme in Scheme::all() {
+            for case in cases() {
+                let signed_at = now().checked_add_signed(-case.age).unwrap();
+                let signed = scheme.sign(signed_at, DELIVERY, BODY);
+                let timestamp = case
+                    .timestamp
+                    .map_or_else(|| signed_at.to_string(), str::to_string);
+                let result = scheme.verify(&timestamp, case.delivery, &signed, case.body);
+                assert_eq!(result, case.expected, "{} with {scheme:?}", case.name);
+            }
+        }
+    }
+
+    #[test]
+    fn delivery_moved_into_body_is_rejected() {
+        // "{timestamp}.{DELIVERY}.x.y" is signed either way, so the id must be a UUID
+        let timestamp = now();
+        let delivery = format!("{DELIVERY}.x");
+        for scheme in Scheme::all() {
+            let signed = scheme.sign(timestamp, DELIVERY, b"x.y");
+            let result = scheme.verify(&timestamp.to_string(), &delivery, &signed, b"y");
+            assert_eq!(result, Err(VerifyError::InvalidDelivery), "{scheme:?}");
+        }
+    }
+
+    #[test]
+    fn generated_delivery_ids_are_accepted() {
+        for _ in 0..100 {
+            assert!(is_delivery_id(&crate::helpers::new_delivery_id()));
+        }
+    }
+
+    #[test]
+    fn other_key_is_rejected() {
+        let timestamp = now();
+        for (signer, verifier) in [
+            (Scheme::hmac("my_secret"), Scheme::hmac("other")),
+            (Scheme::ed25519(), Scheme::ed25519()),
+        ] {
+            let signed = signer.sign(timestamp, DELIVERY, BODY);
+            let result = verifier.verify(&timestamp.to_string(), DELIVERY, &signed, BODY);
+            assert_eq!(result, Err(VerifyError::InvalidSignature), "{signer:?}");
+        }
+    }
+
+    #[test]
+    fn malformed_signature_is_rejected() {
+        let timestamp = now().to_string();
+        for scheme in Scheme::all() {
+            for signed in ["", "not hex", "abc", "abcd"] {
+                let result = scheme.verify(&timestamp, DELIVERY, signed, BODY);
+                assert_eq!(result, Err(VerifyError::InvalidSignature), "{scheme:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn hmac_signature_case_is_ignored() {
+        let scheme = Scheme::hmac("my_secret");
+        let timestamp = now();
+        let signed = scheme.sign(timestamp, DELIVERY, BODY).to_ascii_uppercase();
+        let result = scheme.verify(&timestamp.to_string(), DELIVERY, &signed, BODY);
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn ed25519_invalid_key_is_rejected() {
+        let scheme = Scheme::ed25519();
+        let timestamp = now();
+        let signed = scheme.sign(timestamp, DELIVERY, BODY);
+        let result = verify_ed25519(
+            b"not a key",
+            &timestamp.to_string(),
+            DELIVERY,
+            &signed,
+            BODY,
+            TOLERANCE,
+        );
+        assert_eq!(result, Err(VerifyError::InvalidKey));
+    }
+}
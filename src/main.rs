@@ -8,9 +8,33 @@ use agent_collector::CollectorState;
 use agent_config::{readers::from_toml, types::Config};
 use std::sync::Arc;
 use std::time::Duration;
+use tokio::sync::watch;
 
 const CONFIG_FN: &str = "agent.toml";
 
+/// Wait for SIGINT, SIGTERM or SIGQUIT
+#[cfg(unix)]
+async fn shutdown_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut terminate = signal(SignalKind::terminate()).expect("failed to listen for SIGTERM");
+    let mut quit = signal(SignalKind::quit()).expect("failed to listen for SIGQUIT");
+    let name = tokio::select! {
+        _ = tokio::signal::ctrl_c() => "SIGINT",
+        _ = terminate.recv() => "SIGTERM",
+        _ = quit.recv() => "SIGQUIT",
+    };
+    log::info!("{name} received; shutting down");
+}
+
+/// Wait for CTRL+C
+#[cfg(not(unix))]
+async fn shutdown_signal() {
+    tokio::signal::ctrl_c()
+        .await
+        .expect("failed to listen for exit signal");
+    log::info!("SIGINT received; shutting down");
+}
+
 #[tokio::main]
 async fn main() {
     env_logger::init();
@@ -36,14 +60,43 @@ async fn main() {
         }
     };
 
-    let collector = Arc::new(CollectorState::new(Duration::from_secs(config.cache_for)));
+    let collector = Arc::new(CollectorState::new(&config));
+    if let Some(interval) = config.sampling_interval() {
+        tokio::spawn(CollectorState::run_sampler(
+            collector.clone(),
+            Duration::from_secs(interval),
+        ));
+    }
+
+    // every server is told to stop once a shutdown signal is received
+    let (shutdown_sender, shutdown) = watch::channel(false);
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        shutdown_sender.send_replace(true);
+    });
+    let wait_for_shutdown = |mut shutdown: watch::Receiver<bool>| async move {
+        // an error means the sender is gone, which is also a reason to stop
+        while !*shutdown.borrow_and_update() {
+            if shutdown.changed().await.is_err() {
+                break;
+            }
+        }
+    };
 
     #[cfg(feature = "web")]
-    let web_server = agent_web::run(&config, collector.clone());
+    let web_server = agent_web::run(
+        &config,
+        collector.clone(),
+        wait_for_shutdown(shutdown.clone()),
+    );
 
     // Init Webhook if feature is enabled
     #[cfg(feature = "webhooks")]
-    let webhook_server = agent_webhooks::run(&config, collector.clone());
+    let webhook_server = agent_webhooks::run(
+        &config,
+        collector.clone(),
+        wait_for_shutdown(shutdown.clone()),
+    );
 
     // Send on_start webhook and start server, if feature is enabled
     if cfg!(all(feature = "webhooks", feature = "web")) {
@@ -61,4 +114,5 @@ async fn main() {
         #[cfg(feature = "web")]
         web_server.await.unwrap();
     }
+    collector.flush();
 }